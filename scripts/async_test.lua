@@ -0,0 +1,60 @@
+-- Headless test for async/await in widget scripts
+-- Run with: swaydgets test scripts/async_test.lua
+
+test.http("https://example.com/status", { state = "ok" })
+test.http("https://example.com/missing", "not found", 404)
+
+local window = create_window("Async", 100, 50)
+local label = window:add_box("vertical", 0):add_label("loading", 12)
+
+-- Results are awaited without nesting callbacks
+local task = async(function()
+  local response = await(http.get("https://example.com/missing"))
+  test.assert_eq(response.status, 404, "status is passed through")
+
+  local status = await(http.json("https://example.com/status"))
+  await(sleep(500))
+  label:set_text(status.state)
+  return status.state
+end)
+
+test.advance(0)
+test.assert_eq(test.labels()[1], "loading", "still sleeping")
+test.advance(500)
+test.assert_eq(test.labels()[1], "ok", "label updated after await")
+test.assert_eq(task:done(), true, "task finished")
+
+-- Errors raised by an operation propagate to whoever awaits it
+local failing = async(function()
+  return await(http.json("https://example.com/missing"))
+end)
+local caught
+async(function()
+  local ok, err = pcall(await, failing)
+  caught = not ok and err
+end)
+test.advance(0)
+assert(caught and caught:find("HTTP error: 404"), "error reaches the awaiting task")
+
+-- Cancelled tasks are never resumed
+local cancelled = async(function()
+  await(sleep(100))
+  label:set_text("cancelled task ran")
+end)
+cancelled:cancel()
+test.advance(100)
+test.assert_eq(test.labels()[1], "ok", "cancelled task did not resume")
+
+-- Cancelling a task waiting on an operation that never completes lets its
+-- coroutine be collected
+local coroutines = setmetatable({}, { __mode = "k" })
+local stuck = async(function()
+  coroutines[coroutine.running()] = true
+  await(sleep(1000000))
+end)
+test.advance(0)
+assert(next(coroutines), "task is waiting")
+stuck:cancel()
+collectgarbage()
+collectgarbage()
+test.assert_eq(next(coroutines), nil, "cancelled coroutine released")
@@ -0,0 +1,54 @@
+-- Headless test for the D-Bus API against a private session bus
+-- Run with: dbus-run-session -- swaydgets test scripts/dbus_test.lua
+
+local name = "com.example.SwaydgetsTest"
+
+-- The bus daemon itself answers method calls and property reads, which
+-- complete on the main loop
+local id, features
+local task = async(function()
+  id = await(dbus.call("session", "org.freedesktop.DBus", "/org/freedesktop/DBus",
+    "org.freedesktop.DBus", "GetId"))
+  features = await(dbus.get_property("session", "org.freedesktop.DBus",
+    "/org/freedesktop/DBus", "org.freedesktop.DBus", "Features"))
+end)
+test.dispatch(200)
+test.assert_eq(task:done(), true, "calls completed")
+assert(type(id) == "string" and #id > 0, "GetId returns the bus id")
+assert(type(features) == "table", "arrays become tables")
+
+-- The blocking forms return the reply directly
+test.assert_eq(dbus.call_blocking("session", "org.freedesktop.DBus", "/org/freedesktop/DBus",
+  "org.freedesktop.DBus", "GetId"), id, "blocking call")
+assert(type(dbus.get_property_blocking("session", "org.freedesktop.DBus",
+  "/org/freedesktop/DBus", "org.freedesktop.DBus", "Features")) == "table", "blocking property")
+
+-- Owning a name makes the daemon emit NameOwnerChanged
+local owners = {}
+dbus.on_signal("session", "org.freedesktop.DBus", "/org/freedesktop/DBus",
+  "org.freedesktop.DBus", "NameOwnerChanged", function(changed, old, new)
+    if changed == name then
+      table.insert(owners, new)
+    end
+  end)
+
+local reply = dbus.call_blocking("session", "org.freedesktop.DBus", "/org/freedesktop/DBus",
+  "org.freedesktop.DBus", "RequestName", { name, 0 }, "(su)")
+test.assert_eq(reply, 1, "name acquired")
+
+test.dispatch(200)
+test.assert_eq(#owners, 1, "signal delivered")
+
+-- Failed calls reach whoever awaits them
+local failed
+async(function()
+  local ok, err = pcall(await, dbus.call("session", "org.freedesktop.DBus",
+    "/org/freedesktop/DBus", "org.freedesktop.DBus", "NoSuchMethod"))
+  failed = not ok and err
+end)
+test.dispatch(200)
+assert(failed and failed:find("NoSuchMethod"), "error is passed to await")
+
+-- Wrong argument types are reported instead of sent
+assert(not pcall(dbus.call, "session", "org.freedesktop.DBus", "/org/freedesktop/DBus",
+  "org.freedesktop.DBus", "RequestName", { name, "x" }, "(su)"), "type mismatch")
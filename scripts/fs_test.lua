@@ -0,0 +1,35 @@
+-- Headless test for the fs API
+-- Run with: swaydgets test scripts/fs_test.lua
+
+local path = test.dir .. "/fs_test.log"
+
+-- Clear out anything a failed run left behind, and clean up even when an
+-- assertion fails
+os.remove(path)
+local ok, err = pcall(function()
+    local file = io.open(path, "w")
+    file:write("old line\n")
+    file:close()
+
+    test.assert_eq(fs.read(path), "old line\n", "files can be read")
+
+    local events, lines = {}, {}
+    fs.watch(path, function(event) table.insert(events, event) end)
+    fs.tail(path, function(line) table.insert(lines, line) end)
+
+    -- Only complete lines appended after fs.tail are delivered
+    file = io.open(path, "a")
+    file:write("first\nsecond\npart")
+    file:close()
+    test.fs_event(path, "changed")
+
+    test.assert_eq(events, { "changed" }, "watch sees the change")
+    test.assert_eq(lines, { "first", "second" }, "tail sees the new lines")
+
+    -- Paths outside scripts.fs_allow are refused
+    assert(not pcall(fs.read, "/etc/hostname"), "reads outside the allow list fail")
+end)
+os.remove(path)
+if not ok then
+    error(err, 0)
+end
@@ -49,9 +49,6 @@ local function update_time()
   local current_time = os.date("%H:%M:%S")
   time_label:set_text("Current time: " .. current_time)
   log("Updated time: " .. current_time)
-  
-  -- This function would be called by the schedule_update mechanism
-  -- when it's properly implemented
 end
 
 -- Call it once to initialize
@@ -60,8 +57,7 @@ update_time()
 -- Show the window
 window:show()
 
--- Note: The schedule_update function is commented out in the Rust code
--- When implemented, you would call it like:
--- schedule_update(update_time)
+-- Refresh the time every update interval
+schedule_update(update_time)
 
 log("Hello widget initialized!")
\ No newline at end of file
@@ -0,0 +1,32 @@
+-- Headless test for the hello widget
+-- Run with: swaydgets test scripts/hello_test.lua
+
+-- Give each update a different time, so the test can see updates happen
+local real_date, ticks = os.date, 0
+os.date = function(format, ...)
+    if format == "%H:%M:%S" then
+        ticks = ticks + 1
+        return string.format("00:00:%02d", ticks % 60)
+    end
+    return real_date(format, ...)
+end
+
+test.load("hello.lua")
+
+local windows = test.tree()
+test.assert_eq(#windows, 1, "window count")
+test.assert_eq(windows[1].title, "Hello Widget", "window title")
+test.assert_eq(windows[1].margin_top, 20, "top margin")
+test.assert_eq(windows[1].visible, true, "window shown")
+
+local labels = test.labels()
+test.assert_eq(labels[1], "Hello, World!", "greeting")
+assert(test.find_label("^Current time: %d%d:%d%d:%d%d$"), "time label filled in")
+
+-- The update timer keeps running on the virtual clock, once a second
+local before = test.find_label("^Current time: ")
+test.advance(1500)
+local after = test.find_label("^Current time: ")
+assert(after and after ~= before, "time label updated")
+
+os.date = real_date
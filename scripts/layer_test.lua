@@ -0,0 +1,38 @@
+-- Headless test for layer shell placement
+-- Run with: swaydgets test scripts/layer_test.lua
+
+local window = create_window("Placement", 100, 50)
+test.assert_eq(test.tree()[1].layer, "background", "default layer")
+
+window:set_layer("overlay")
+window:set_namespace("clock")
+window:set_exclusive_zone(30)
+window:set_anchor({ top = true, left = true, right = true })
+window:show()
+
+local props = test.tree()[1]
+test.assert_eq(props.layer, "overlay", "layer")
+test.assert_eq(props.namespace, "clock", "namespace")
+test.assert_eq(props.exclusive_zone, 30, "exclusive zone")
+test.assert_eq({ props.anchor_top, props.anchor_bottom, props.anchor_left, props.anchor_right },
+  { true, false, true, true }, "anchors")
+
+window:set_position("bottom-right")
+props = test.tree()[1]
+test.assert_eq({ props.anchor_top, props.anchor_bottom, props.anchor_left, props.anchor_right },
+  { false, true, false, true }, "corner position")
+
+window:set_position("center")
+props = test.tree()[1]
+test.assert_eq(props.anchor_bottom or props.anchor_right, false, "centered")
+
+assert(not pcall(window.set_layer, window, "middle"), "unknown layer")
+assert(not pcall(window.set_position, window, "top-middle"), "unknown position")
+
+-- Click-through windows keep interactive boxes clickable
+local buttons = window:add_box("horizontal", 0)
+buttons:set_interactive(true)
+window:set_click_through(true)
+props = test.tree()[1]
+test.assert_eq(props.click_through, true, "click-through")
+test.assert_eq(props.children[1].interactive, true, "interactive box")
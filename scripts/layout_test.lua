@@ -0,0 +1,33 @@
+-- Headless test for dragging windows in edit-layout mode
+-- Run with: swaydgets test scripts/layout_test.lua
+
+local window = create_window("Notes", 120, 80)
+window:set_margin("top", 20)
+window:set_margin("left", 30)
+assert(not pcall(test.drag, 1, 10, 10), "not draggable yet")
+
+window:set_draggable()
+window:show()
+test.assert_eq(test.tree()[1].draggable, true, "draggable")
+
+test.drag(1, 15, -5)
+local props = test.tree()[1]
+test.assert_eq({ props.margin_left, props.margin_top }, { 45, 15 }, "dragged")
+
+-- Margins never go past the anchored edge
+test.drag(1, -100, 0)
+test.assert_eq(test.tree()[1].margin_left, 0, "clamped")
+
+-- Bottom-right windows move the other way
+window:set_position("bottom-right")
+window:set_margin("bottom", 10)
+test.drag(1, -20, -20)
+props = test.tree()[1]
+test.assert_eq({ props.margin_right, props.margin_bottom }, { 20, 30 }, "corner drag")
+
+-- A window with the same title comes back where it was dragged
+local again = create_window("Notes", 120, 80)
+again:set_position("bottom-right")
+again:set_draggable()
+props = test.tree()[2]
+test.assert_eq({ props.margin_right, props.margin_bottom }, { 20, 30 }, "restored")
@@ -0,0 +1,42 @@
+-- Headless test for per-output windows
+-- Run with: swaydgets test scripts/outputs_test.lua
+
+-- Windows still open, keyed by "title@output"
+local function open_windows()
+  local open = {}
+  for _, window in ipairs(test.tree()) do
+    if not window.closed then
+      open[window.title .. "@" .. window.output] = window
+    end
+  end
+  return open
+end
+
+test.outputs({ "DP-1" })
+
+local clock = create_window("Clock", 100, 50, { output = "all" })
+clock:set_layer("top")
+local label = clock:add_box("vertical", 0):add_label("12:00", 12)
+clock:show()
+
+local pinned = create_window("Pinned", 100, 50, { output = "HDMI-A-1" })
+pinned:show()
+test.assert_eq(open_windows()["Pinned@HDMI-A-1"], nil, "nothing on a missing output")
+
+-- Plugging a monitor in replays the window on it
+test.outputs({ "DP-1", "HDMI-A-1" })
+local open = open_windows()
+assert(open["Clock@DP-1"], "first instance kept")
+test.assert_eq(open["Clock@HDMI-A-1"].layer, "top", "settings replayed")
+test.assert_eq(open["Clock@HDMI-A-1"].visible, true, "new instance shown")
+assert(open["Pinned@HDMI-A-1"], "named output appears")
+test.assert_eq(open["Pinned@DP-1"], nil, "only on the named output")
+
+label:set_text("12:01")
+test.assert_eq(test.labels(), { "12:01", "12:01" }, "updates reach every instance")
+
+-- Unplugging closes the instances on that output
+test.outputs({ "HDMI-A-1" })
+open = open_windows()
+test.assert_eq(open["Clock@DP-1"], nil, "instance closed on unplug")
+assert(open["Clock@HDMI-A-1"], "other output untouched")
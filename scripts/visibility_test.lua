@@ -0,0 +1,32 @@
+-- Headless test for showing and hiding widgets by name
+-- Run with: swaydgets test scripts/visibility_test.lua
+
+local clock = create_window("Clock", 100, 50, { name = "clock" })
+clock:set_transition("fade", 150)
+local props = test.tree()[1]
+test.assert_eq({ props.transition, props.transition_duration }, { "fade", 150 }, "transition")
+assert(not pcall(clock.set_transition, clock, "spin"), "unknown transition")
+
+-- Hiding before the first show keeps the window off screen
+widgets.hide("clock")
+test.assert_eq(test.tree()[1].visible, false, "not shown yet")
+clock:show()
+test.assert_eq(test.tree()[1].visible, false, "shown while hidden")
+
+widgets.show("clock")
+test.assert_eq(test.tree()[1].visible, true, "shown")
+test.assert_eq(widgets.is_visible("clock"), true, "is_visible")
+
+test.assert_eq(clock:toggle(), false, "toggle returns visibility")
+test.assert_eq(test.tree()[1].visible, false, "toggled off")
+clock:toggle()
+test.assert_eq(test.tree()[1].visible, true, "toggled on")
+
+-- Windows are named after their title by default
+local notes = create_window("Notes", 100, 50)
+notes:show()
+test.assert_eq(widgets.list(), { "Notes", "clock" }, "names")
+notes:hide()
+test.assert_eq({ test.tree()[1].visible, test.tree()[2].visible }, { true, false }, "hide one")
+
+assert(not pcall(widgets.toggle, "missing"), "unknown widget")
@@ -0,0 +1,495 @@
+use gtk::prelude::*;
+use gtk::{Application, ApplicationWindow, Box as GtkBox, Label, OffscreenWindow, Orientation};
+use gtk::{gdk, gio, glib};
+use gtk_layer_shell::{Edge, Layer, LayerShell};
+use log::{debug, error, info, warn};
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::visibility::{self, Transition};
+use crate::{input, layout, outputs};
+
+/// Result of a command run through `exec`
+#[derive(Clone, Debug)]
+pub struct ExecOutput {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Response to an HTTP request
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Everything a Lua script can reach outside of the Lua state itself.
+///
+/// `GtkBackend` drives real layer-shell windows, while the test harness
+/// provides a mocked implementation with a virtual clock and fixtures.
+pub trait Backend {
+    /// Create a window, on the named output if there is one
+    fn create_window(
+        &self,
+        title: &str,
+        width: i32,
+        height: i32,
+        output: Option<&str>,
+    ) -> Rc<dyn WindowHandle>;
+
+    /// Names of the connected outputs, such as `DP-1`
+    fn outputs(&self) -> Vec<String>;
+
+    /// Call `callback` when outputs are connected or disconnected, until the
+    /// returned watch is dropped
+    fn watch_outputs(&self, callback: Box<dyn Fn()>) -> Result<Box<dyn Watch>, String>;
+
+    /// Call `callback` every `interval` until it returns false
+    fn add_timeout(&self, interval: Duration, callback: Box<dyn FnMut() -> bool>);
+
+    /// Fetch the body of `url`, failing on non-success statuses
+    fn http_get(&self, url: &str) -> Result<String, String>;
+
+    /// Request `url` in the background, calling `done` on the main loop
+    fn http_get_async(&self, url: &str, done: Box<dyn FnOnce(Result<HttpResponse, String>)>);
+
+    /// Run a shell command and collect its output
+    fn exec(&self, command: &str) -> Result<ExecOutput, String>;
+
+    /// Run a shell command in the background, calling `done` on the main loop
+    fn exec_async(&self, command: &str, done: Box<dyn FnOnce(Result<ExecOutput, String>)>);
+
+    /// Watch a file or directory, calling `callback` with the event name
+    /// (`changed`, `done`, `created`, `deleted` or `renamed`) and the affected
+    /// path until the returned watch is dropped
+    fn watch(&self, path: &Path, callback: WatchCallback) -> Result<Box<dyn Watch>, String>;
+
+    /// Report an error raised by a script outside of its initial run
+    fn script_error(&self, message: String);
+}
+
+/// A file watch or other subscription, stopped when dropped
+pub trait Watch {}
+
+/// Called with the event name and the affected path of a watch
+pub type WatchCallback = Box<dyn Fn(&str, &Path)>;
+
+/// Called with a dragged window's new position
+pub type MoveCallback = Box<dyn Fn(i32, i32)>;
+
+/// A window created by a script
+pub trait WindowHandle {
+    fn set_margin(&self, edge: Edge, margin: i32);
+    fn set_layer(&self, layer: Layer);
+    fn set_anchor(&self, edge: Edge, anchored: bool);
+    fn set_exclusive_zone(&self, zone: i32);
+    /// Only takes effect before the window is first shown
+    fn set_namespace(&self, namespace: &str);
+    /// Let pointer input through, except over interactive boxes
+    fn set_click_through(&self, enabled: bool);
+    /// Let the window be dragged in edit-layout mode, starting at a saved
+    /// position. `on_moved` gets the margins of the anchored edges.
+    fn make_draggable(&self, position: Option<(i32, i32)>, on_moved: MoveCallback);
+    /// How the window appears and disappears
+    fn set_transition(&self, transition: Transition);
+    fn show(&self);
+    fn hide(&self);
+    fn close(&self);
+    fn add_box(&self, orientation: Orientation, spacing: i32) -> Rc<dyn BoxHandle>;
+}
+
+/// A box container inside a script window
+pub trait BoxHandle {
+    fn add_label(&self, text: &str, font_size: i32) -> Rc<dyn LabelHandle>;
+    fn set_css(&self, css: &str);
+    /// Keep receiving pointer input while the window is click-through
+    fn set_interactive(&self, interactive: bool);
+}
+
+/// A label inside a script box
+pub trait LabelHandle {
+    fn set_text(&self, text: &str);
+    fn set_css(&self, css: &str);
+}
+
+/// Backend used when running as a desktop widget host
+pub struct GtkBackend {
+    app: Option<Application>,
+    offscreen: RefCell<Vec<OffscreenWindow>>,
+}
+
+impl GtkBackend {
+    pub fn new(app: &Application) -> Self {
+        GtkBackend {
+            app: Some(app.clone()),
+            offscreen: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// A backend building script windows offscreen, for rendering snapshots
+    pub fn offscreen() -> Self {
+        GtkBackend {
+            app: None,
+            offscreen: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Windows created by an offscreen backend, in creation order
+    pub fn offscreen_windows(&self) -> Vec<OffscreenWindow> {
+        self.offscreen.borrow().clone()
+    }
+}
+
+impl Backend for GtkBackend {
+    fn create_window(
+        &self,
+        title: &str,
+        width: i32,
+        height: i32,
+        output: Option<&str>,
+    ) -> Rc<dyn WindowHandle> {
+        info!("Creating window: {}", title);
+        let window: gtk::Window = match &self.app {
+            Some(app) => {
+                let window = ApplicationWindow::builder()
+                    .application(app)
+                    .title(title)
+                    .default_width(width)
+                    .default_height(height)
+                    .build();
+
+                window.init_layer_shell();
+                window.set_namespace("swaydgets");
+                window.set_layer(Layer::Background);
+                window.set_anchor(Edge::Top, true);
+                window.set_anchor(Edge::Left, true);
+                if let Some(output) = output {
+                    match outputs::monitors()
+                        .into_iter()
+                        .find(|(name, _)| name == output)
+                    {
+                        Some((_, monitor)) => window.set_monitor(&monitor),
+                        None => warn!("Output {} not found for window {}", output, title),
+                    }
+                }
+                window.upcast()
+            }
+            None => {
+                let window = OffscreenWindow::new();
+                window.set_title(title);
+                window.set_default_size(width, height);
+                self.offscreen.borrow_mut().push(window.clone());
+                window.upcast()
+            }
+        };
+
+        // Make window transparent
+        window.set_app_paintable(true);
+        window.connect_draw(|_, cr| {
+            cr.set_source_rgba(0.0, 0.0, 0.0, 0.0);
+            cr.paint().unwrap();
+            false.into()
+        });
+
+        Rc::new(GtkWindow {
+            window,
+            click_through: RefCell::new(None),
+            transition: Cell::new(Transition::None),
+        })
+    }
+
+    fn outputs(&self) -> Vec<String> {
+        outputs::monitors()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn watch_outputs(&self, callback: Box<dyn Fn()>) -> Result<Box<dyn Watch>, String> {
+        let display = gdk::Display::default().ok_or("No display")?;
+
+        // Wait for GDK to finish updating its monitor list
+        let callback: Rc<dyn Fn()> = Rc::from(callback);
+        let on_change = move |_: &gdk::Display, _: &gdk::Monitor| {
+            let callback = callback.clone();
+            glib::idle_add_local_once(move || callback());
+        };
+        let handlers = vec![
+            display.connect_monitor_added(on_change.clone()),
+            display.connect_monitor_removed(on_change),
+        ];
+        Ok(Box::new(GtkOutputsWatch { display, handlers }))
+    }
+
+    fn add_timeout(&self, interval: Duration, mut callback: Box<dyn FnMut() -> bool>) {
+        glib::timeout_add_local(interval, move || callback().into());
+    }
+
+    fn http_get(&self, url: &str) -> Result<String, String> {
+        let response = http_request(url)?;
+        if !(200..300).contains(&response.status) {
+            return Err(format!("HTTP error: {}", response.status));
+        }
+        Ok(response.body)
+    }
+
+    fn http_get_async(&self, url: &str, done: Box<dyn FnOnce(Result<HttpResponse, String>)>) {
+        let url = url.to_string();
+        spawn_worker(move || http_request(&url), done);
+    }
+
+    fn exec(&self, command: &str) -> Result<ExecOutput, String> {
+        run_command(command)
+    }
+
+    fn exec_async(&self, command: &str, done: Box<dyn FnOnce(Result<ExecOutput, String>)>) {
+        let command = command.to_string();
+        spawn_worker(move || run_command(&command), done);
+    }
+
+    fn watch(&self, path: &Path, callback: WatchCallback) -> Result<Box<dyn Watch>, String> {
+        let file = gio::File::for_path(path);
+        let monitor = if path.is_dir() {
+            file.monitor_directory(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+        } else {
+            file.monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+        }
+        .map_err(|e| format!("Failed to watch {}: {}", path.display(), e))?;
+
+        monitor.connect_changed(move |_, file, _, event| {
+            let event = match event {
+                gio::FileMonitorEvent::Changed => "changed",
+                gio::FileMonitorEvent::ChangesDoneHint => "done",
+                gio::FileMonitorEvent::Created | gio::FileMonitorEvent::MovedIn => "created",
+                gio::FileMonitorEvent::Deleted | gio::FileMonitorEvent::MovedOut => "deleted",
+                gio::FileMonitorEvent::Moved | gio::FileMonitorEvent::Renamed => "renamed",
+                _ => return,
+            };
+            if let Some(path) = file.path() {
+                callback(event, &path);
+            }
+        });
+
+        Ok(Box::new(GtkWatch(monitor)))
+    }
+
+    fn script_error(&self, message: String) {
+        error!("[Lua] {}", message);
+    }
+}
+
+// A gio file monitor, backed by inotify on Linux
+struct GtkWatch(gio::FileMonitor);
+
+impl Watch for GtkWatch {}
+
+impl Drop for GtkWatch {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+// Monitor signal handlers, disconnected when dropped
+struct GtkOutputsWatch {
+    display: gdk::Display,
+    handlers: Vec<glib::SignalHandlerId>,
+}
+
+impl Watch for GtkOutputsWatch {}
+
+impl Drop for GtkOutputsWatch {
+    fn drop(&mut self) {
+        for handler in self.handlers.drain(..) {
+            self.display.disconnect(handler);
+        }
+    }
+}
+
+// Run blocking work on a worker thread and hand the result to the main loop
+fn spawn_worker<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, String> + Send + 'static,
+    done: Box<dyn FnOnce(Result<T, String>)>,
+) {
+    let handle = gio::spawn_blocking(work);
+    glib::MainContext::default().spawn_local(async move {
+        let result = handle
+            .await
+            .unwrap_or_else(|_| Err("Background worker panicked".to_string()));
+        done(result);
+    });
+}
+
+fn http_request(url: &str) -> Result<HttpResponse, String> {
+    info!("Fetching: {}", url);
+    let response =
+        reqwest::blocking::get(url).map_err(|err| format!("Failed to fetch URL: {}", err))?;
+    let status = response.status().as_u16();
+    let body = response
+        .text()
+        .map_err(|err| format!("Failed to read response: {}", err))?;
+    Ok(HttpResponse { status, body })
+}
+
+fn run_command(command: &str) -> Result<ExecOutput, String> {
+    debug!("Executing: {}", command);
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .map_err(|err| format!("Failed to run command: {}", err))?;
+    Ok(ExecOutput {
+        status: output.status.code().unwrap_or(-1),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    })
+}
+
+struct GtkWindow {
+    window: gtk::Window,
+    click_through: RefCell<Option<glib::SignalHandlerId>>,
+    transition: Cell<Transition>,
+}
+
+impl GtkWindow {
+    // The window as a layer surface; offscreen windows have no placement
+    fn layer_surface(&self) -> Option<&gtk::Window> {
+        (!self.window.is::<OffscreenWindow>()).then_some(&self.window)
+    }
+}
+
+impl WindowHandle for GtkWindow {
+    fn set_margin(&self, edge: Edge, margin: i32) {
+        debug!("Setting margin: {:?} {}", edge, margin);
+        if let Some(window) = self.layer_surface() {
+            window.set_layer_shell_margin(edge, margin);
+        }
+    }
+
+    fn set_layer(&self, layer: Layer) {
+        debug!("Setting layer: {:?}", layer);
+        if let Some(window) = self.layer_surface() {
+            window.set_layer(layer);
+        }
+    }
+
+    fn set_anchor(&self, edge: Edge, anchored: bool) {
+        debug!("Setting anchor: {:?} {}", edge, anchored);
+        if let Some(window) = self.layer_surface() {
+            window.set_anchor(edge, anchored);
+        }
+    }
+
+    fn set_exclusive_zone(&self, zone: i32) {
+        debug!("Setting exclusive zone: {}", zone);
+        if let Some(window) = self.layer_surface() {
+            window.set_exclusive_zone(zone);
+        }
+    }
+
+    fn set_namespace(&self, namespace: &str) {
+        debug!("Setting namespace: {}", namespace);
+        if let Some(window) = self.layer_surface() {
+            window.set_namespace(namespace);
+        }
+    }
+
+    fn set_click_through(&self, enabled: bool) {
+        debug!("Setting click-through: {}", enabled);
+        let mut click_through = self.click_through.borrow_mut();
+        match (enabled, click_through.take()) {
+            (true, None) => *click_through = Some(input::enable_click_through(&self.window)),
+            (false, Some(handler)) => input::disable_click_through(&self.window, handler),
+            (_, handler) => *click_through = handler,
+        }
+    }
+
+    fn make_draggable(&self, position: Option<(i32, i32)>, on_moved: MoveCallback) {
+        if let Some(window) = self.layer_surface() {
+            if let Some((x, y)) = position {
+                layout::move_to(window, x, y);
+            }
+            layout::make_draggable(window, on_moved);
+        }
+    }
+
+    fn set_transition(&self, transition: Transition) {
+        debug!("Setting transition: {:?}", transition);
+        self.transition.set(transition);
+    }
+
+    fn show(&self) {
+        match self.layer_surface() {
+            Some(window) => visibility::show_window(window, self.transition.get()),
+            None => self.window.show_all(),
+        }
+    }
+
+    fn hide(&self) {
+        match self.layer_surface() {
+            Some(window) => visibility::hide_window(window, self.transition.get()),
+            None => self.window.hide(),
+        }
+    }
+
+    fn close(&self) {
+        self.window.close();
+    }
+
+    fn add_box(&self, orientation: Orientation, spacing: i32) -> Rc<dyn BoxHandle> {
+        let container = GtkBox::new(orientation, spacing);
+        self.window.add(&container);
+        Rc::new(GtkContainer(container))
+    }
+}
+
+struct GtkContainer(GtkBox);
+
+impl BoxHandle for GtkContainer {
+    fn add_label(&self, text: &str, font_size: i32) -> Rc<dyn LabelHandle> {
+        let label = Label::new(Some(text));
+
+        // Apply CSS for font size
+        add_css(
+            &label,
+            &format!("label {{ font-size: {}px; color: white; }}", font_size),
+        );
+
+        self.0.pack_start(&label, true, true, 0);
+        Rc::new(GtkLabel(label))
+    }
+
+    fn set_css(&self, css: &str) {
+        add_css(&self.0, css);
+    }
+
+    fn set_interactive(&self, interactive: bool) {
+        input::set_interactive(&self.0, interactive);
+    }
+}
+
+struct GtkLabel(Label);
+
+impl LabelHandle for GtkLabel {
+    fn set_text(&self, text: &str) {
+        self.0.set_text(text);
+    }
+
+    fn set_css(&self, css: &str) {
+        add_css(&self.0, css);
+    }
+}
+
+// Attach a CSS snippet to a single widget
+fn add_css(widget: &impl IsA<gtk::Widget>, css: &str) {
+    let css_provider = gtk::CssProvider::new();
+    if let Err(e) = css_provider.load_from_data(css.as_bytes()) {
+        error!("Invalid CSS: {}", e);
+        return;
+    }
+    widget
+        .style_context()
+        .add_provider(&css_provider, gtk::STYLE_PROVIDER_PRIORITY_APPLICATION);
+}
@@ -1,47 +1,116 @@
-use chrono::{Datelike, Local};
+use chrono::{Datelike, Local, NaiveDate};
 use gtk::prelude::*;
 use gtk::{Application, ApplicationWindow, Button, Calendar, Orientation};
 use gtk_layer_shell::{Edge, Layer, LayerShell};
 use log::info;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
-use crate::config::CalendarConfig;
+use crate::config::{self, CalendarConfig, Position};
+use crate::visibility::{self, Toggle, Transition};
+use crate::{input, layout, outputs};
+
+// Name used to show and hide the calendar
+const NAME: &str = "calendar";
+
+// A calendar window, one per output
+struct CalendarWindow {
+    window: gtk::Window,
+    transition: Transition,
+}
+
+impl Toggle for CalendarWindow {
+    fn set_visible(&self, visible: bool) {
+        if visible {
+            visibility::show_window(&self.window, self.transition);
+        } else {
+            visibility::hide_window(&self.window, self.transition);
+        }
+    }
+}
 
 pub fn create_calendar(app: &Application, config: &CalendarConfig) {
     info!("Creating calendar widget");
 
-    // Create window
-    let window = ApplicationWindow::builder()
-        .application(app)
-        .title("Sway Calendar")
-        .default_width(config.size.width)
-        .default_height(config.size.height)
-        .build();
-
-    // Layer shell setup
-    window.init_layer_shell();
-    window.set_layer(Layer::Background);
-    window.auto_exclusive_zone_enable();
-    window.set_size_request(config.size.width, config.size.height);
-    window.set_layer_shell_margin(Edge::Top, config.position.y);
-    window.set_layer_shell_margin(Edge::Left, config.position.x);
-
-    // Set app paintable for transparent background
-    window.set_app_paintable(true);
-    window.connect_draw(|_, cr| {
-        cr.set_source_rgba(0.0, 0.0, 0.0, 0.0);
-        cr.paint().unwrap();
-        false.into()
+    let app = app.clone();
+    // Shared so windows created on hotplug open where the calendar was dragged
+    let position = Rc::new(Cell::new(config.position));
+    let size = config.size;
+    let click_through = config.click_through;
+    let transition = config.transition.to_transition(config.transition_duration);
+    outputs::instantiate(config.output.as_deref(), move |monitor, _| {
+        // Create window
+        let window = ApplicationWindow::builder()
+            .application(&app)
+            .title("Sway Calendar")
+            .default_width(size.width)
+            .default_height(size.height)
+            .build();
+
+        // Layer shell setup
+        window.init_layer_shell();
+        outputs::place(&window, monitor);
+        window.set_layer(Layer::Background);
+        window.auto_exclusive_zone_enable();
+        window.set_size_request(size.width, size.height);
+        window.set_anchor(Edge::Top, true);
+        window.set_anchor(Edge::Left, true);
+        let start = position.get();
+        window.set_layer_shell_margin(Edge::Top, start.y);
+        window.set_layer_shell_margin(Edge::Left, start.x);
+
+        // Set app paintable for transparent background
+        window.set_app_paintable(true);
+        window.connect_draw(|_, cr| {
+            cr.set_source_rgba(0.0, 0.0, 0.0, 0.0);
+            cr.paint().unwrap();
+            false.into()
+        });
+
+        window.add(&build_calendar(|| Local::now().date_naive()));
+        if click_through {
+            input::enable_click_through(window.upcast_ref());
+        }
+
+        // Save the new position when dragged in edit-layout mode
+        let position = position.clone();
+        layout::make_draggable(window.upcast_ref(), move |x, y| {
+            position.set(Position { x, y });
+            config::update_config(|config| config.calendar.position = Position { x, y });
+        });
+
+        // Reachable by name until the window goes away with its output
+        let registration = visibility::register(
+            NAME,
+            Rc::new(CalendarWindow {
+                window: window.clone().upcast(),
+                transition,
+            }),
+        );
+        let registration = RefCell::new(Some(registration));
+        window.connect_destroy(move |_| drop(registration.take()));
+
+        if visibility::is_visible(NAME) {
+            visibility::show_window(window.upcast_ref(), transition);
+        } else {
+            window.child().inspect(|child| child.show_all());
+        }
+        vec![window.upcast()]
     });
+}
 
+// Build the calendar contents and load its styling. `today` tells the date
+// to open on and to return to.
+pub fn build_calendar(today: impl Fn() -> NaiveDate + 'static) -> gtk::Box {
     // Main container
     let vbox = gtk::Box::new(Orientation::Vertical, 10);
     vbox.set_margin(12);
 
     // Get current date
-    let today = Local::now().date_naive();
-    let year = today.year() as i32;
-    let month = today.month() as i32 - 1; // Calendar months are 0-indexed
-    let day = today.day() as i32;
+    let date = today();
+    let year = date.year();
+    let month = date.month() as i32 - 1; // Calendar months are 0-indexed
+    let day = date.day() as i32;
 
     // Create calendar widget
     let calendar = Calendar::new();
@@ -52,7 +121,7 @@ pub fn create_calendar(app: &Application, config: &CalendarConfig) {
     );
 
     // Set calendar to start week on Monday (1 = Monday, 0 = Sunday)
-    calendar.set_property("show-details", &false);
+    calendar.set_property("show-details", false);
 
     // Set current date
     calendar.select_month(month as u32, year as u32);
@@ -67,6 +136,11 @@ pub fn create_calendar(app: &Application, config: &CalendarConfig) {
     let next_button = Button::with_label("Next ▶");
     let today_button = Button::with_label("Today");
 
+    // The buttons stay clickable when the calendar is click-through
+    for button in [&prev_button, &next_button, &today_button] {
+        input::set_interactive(button, true);
+    }
+
     hbox.pack_start(&prev_button, true, true, 5);
     hbox.pack_start(&today_button, true, true, 5);
     hbox.pack_start(&next_button, true, true, 5);
@@ -94,10 +168,10 @@ pub fn create_calendar(app: &Application, config: &CalendarConfig) {
 
     let calendar_clone = calendar.clone();
     today_button.connect_clicked(move |_| {
-        let today = Local::now().date_naive();
-        let year = today.year() as u32;
-        let month = today.month() as u32 - 1;
-        let day = today.day() as u32;
+        let date = today();
+        let year = date.year() as u32;
+        let month = date.month() - 1;
+        let day = date.day();
         calendar_clone.select_month(month, year);
         calendar_clone.select_day(day);
     });
@@ -156,6 +230,5 @@ pub fn create_calendar(app: &Application, config: &CalendarConfig) {
     vbox.pack_start(&calendar, true, true, 0);
     vbox.pack_end(&hbox, false, false, 5);
 
-    window.add(&vbox);
-    window.show_all();
+    vbox
 }
@@ -0,0 +1,99 @@
+use chrono::NaiveDate;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage:
+    swaydgets                      Run the widgets
+    swaydgets test <script.lua>... Run Lua widget tests headlessly
+    swaydgets edit-layout          Toggle dragging widgets into place in the running instance
+    swaydgets show|hide|toggle <name>
+                                   Change a widget's visibility in the running instance
+    swaydgets render --widget <calendar|dock|dock-preview|script> --out <file.png> [--size WxH]
+                     [--date YYYY-MM-DD]
+                                   Render a widget to a PNG snapshot, the calendar showing
+                                   the given date or a fixed one";
+
+/// What the process was asked to do
+pub enum Command {
+    Run,
+    Test(Vec<PathBuf>),
+    Render {
+        widget: String,
+        out: PathBuf,
+        size: Option<(i32, i32)>,
+        date: Option<NaiveDate>,
+    },
+    EditLayout,
+    /// Show, hide or toggle the named widget
+    Visibility {
+        action: String,
+        name: String,
+    },
+    Help,
+}
+
+// Parse the command line arguments (without the program name)
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    match args.next().as_deref() {
+        None => Ok(Command::Run),
+        Some("-h") | Some("--help") | Some("help") => Ok(Command::Help),
+        Some("test") => {
+            let scripts: Vec<PathBuf> = args.map(PathBuf::from).collect();
+            if scripts.is_empty() {
+                return Err("test needs at least one script".to_string());
+            }
+            Ok(Command::Test(scripts))
+        }
+        Some("render") => parse_render(args),
+        Some("edit-layout") => Ok(Command::EditLayout),
+        Some(action @ ("show" | "hide" | "toggle")) => {
+            let name = args
+                .next()
+                .ok_or_else(|| format!("{} needs a widget name", action))?;
+            Ok(Command::Visibility {
+                action: action.to_string(),
+                name,
+            })
+        }
+        Some(other) => Err(format!("Unknown command: {}", other)),
+    }
+}
+
+fn parse_render(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut widget = None;
+    let mut out = None;
+    let mut size = None;
+    let mut date = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--widget" => widget = Some(value()?),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--size" => size = Some(parse_size(&value()?)?),
+            "--date" => date = Some(parse_date(&value()?)?),
+            other => return Err(format!("Unknown render option: {}", other)),
+        }
+    }
+
+    Ok(Command::Render {
+        widget: widget.ok_or("render needs --widget")?,
+        out: out.ok_or("render needs --out")?,
+        size,
+        date,
+    })
+}
+
+// Parse a WxH size such as 300x250
+fn parse_size(size: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("Invalid size {}, expected WxH", size);
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let width = width.parse().map_err(|_| invalid())?;
+    let height = height.parse().map_err(|_| invalid())?;
+    Ok((width, height))
+}
+
+// Parse a date such as 2024-03-15
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date {}, expected YYYY-MM-DD", date))
+}
@@ -1,10 +1,13 @@
 use gtk_layer_shell::Edge;
 use log::{error, info};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
+use crate::visibility::Transition;
+
 // Main configuration structure
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
@@ -12,6 +15,8 @@ pub struct Config {
     pub dock: DockConfig,
     #[serde(default)]
     pub calendar: CalendarConfig,
+    #[serde(default)]
+    pub scripts: ScriptsConfig,
 }
 
 // Default implementation for Config
@@ -20,6 +25,7 @@ impl Default for Config {
         Self {
             dock: DockConfig::default(),
             calendar: CalendarConfig::default(),
+            scripts: ScriptsConfig::default(),
         }
     }
 }
@@ -30,6 +36,124 @@ pub struct DockConfig {
     pub enabled: bool,
     pub edge: EdgeConfig,
     pub hide_timeout: u64, // in milliseconds
+    #[serde(default)]
+    pub reveal_delay: u64, // in milliseconds the pointer rests on the edge before the dock shows
+    // Stands in for a pressure threshold: layer shell has no pointer
+    // barriers, so pushing past the edge cannot be measured
+    #[serde(default)]
+    pub reveal_travel: u32, // pixels the pointer moves along the edge before the dock shows, 0 for none
+    #[serde(default)]
+    pub output: Option<String>, // output name such as "DP-1" or "all", unset lets Sway choose
+    #[serde(default)]
+    pub pinned: Vec<String>, // app ids always shown in the dock, launched when not running
+    #[serde(default)]
+    pub group_windows: bool, // one button per app, clicks cycle its windows, long press lists them
+    #[serde(default)]
+    pub apps: HashMap<String, AppOverride>, // per-app settings keyed by app id, over desktop entries
+    #[serde(default)]
+    pub filter: DockFilter, // which windows each output's dock shows
+    #[serde(default)]
+    pub workspaces: bool, // show a workspace switcher before the window buttons
+    #[serde(default)]
+    pub autohide: AutohideConfig, // when the dock gets out of the way
+    #[serde(default = "default_icon_size")]
+    pub icon_size: i32, // in pixels
+    #[serde(default = "default_spacing")]
+    pub spacing: i32, // pixels between buttons
+    #[serde(default = "default_padding")]
+    pub padding: i32, // pixels around the buttons
+    #[serde(default)]
+    pub labels: LabelConfig,
+    #[serde(default = "default_label_width")]
+    pub label_width: i32, // in characters, longer titles are ellipsized
+    #[serde(default)]
+    pub sizing: SizingConfig,
+    #[serde(default)]
+    pub exclusive_zone: bool, // keep windows clear of the dock, needs autohide = "never"
+    #[serde(default = "default_magnification")]
+    pub magnification: f64, // icon scale under the pointer, 1.0 turns zooming off
+    #[serde(default = "default_magnification_range")]
+    pub magnification_range: i32, // pixels from the pointer within which icons grow
+    #[serde(default = "default_magnify_vertical")]
+    pub magnify_vertical: bool, // also zoom on docks at the left or right edge
+    #[serde(default)]
+    pub previews: bool, // show a window preview when hovering a button, captured with grim
+}
+
+fn default_magnification() -> f64 {
+    1.0
+}
+
+fn default_magnification_range() -> i32 {
+    120
+}
+
+fn default_magnify_vertical() -> bool {
+    true
+}
+
+fn default_icon_size() -> i32 {
+    32
+}
+
+fn default_spacing() -> i32 {
+    15
+}
+
+fn default_padding() -> i32 {
+    5
+}
+
+fn default_label_width() -> i32 {
+    10
+}
+
+// Label configuration - where dock buttons show their titles
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelConfig {
+    None, // icons only
+    #[default]
+    Below, // under the icon, or beside it on a vertical dock
+    Tooltip, // on hover
+}
+
+// Sizing configuration - how much of its edge the dock takes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SizingConfig {
+    Fit, // as long as its buttons
+    #[default]
+    Span, // the whole edge
+}
+
+// Autohide configuration - when the dock hides until its edge is hovered
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AutohideConfig {
+    Never, // always shown
+    #[default]
+    Always, // shown only while hovered
+    Intelligent, // hidden while a window overlaps the dock
+    Fullscreen, // hidden while a window is fullscreen on the dock's output
+}
+
+// Dock filter - which windows a dock on one output shows
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DockFilter {
+    #[default]
+    All, // every window
+    Output,    // windows on the dock's output
+    Workspace, // windows on the workspace shown on the dock's output
+}
+
+// Per-app dock settings, each replacing what the app's desktop entry says
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppOverride {
+    pub name: Option<String>,
+    pub icon: Option<String>, // icon name or image path
+    pub exec: Option<String>, // command line run by `swaymsg exec`
 }
 
 // Default implementation for DockConfig
@@ -39,6 +163,26 @@ impl Default for DockConfig {
             enabled: false, // Disabled by default
             edge: EdgeConfig::Bottom,
             hide_timeout: 300,
+            reveal_delay: 0,
+            reveal_travel: 0,
+            output: None,
+            pinned: Vec::new(),
+            group_windows: false,
+            apps: HashMap::new(),
+            filter: DockFilter::All,
+            workspaces: false,
+            autohide: AutohideConfig::Always,
+            icon_size: default_icon_size(),
+            spacing: default_spacing(),
+            padding: default_padding(),
+            labels: LabelConfig::Below,
+            label_width: default_label_width(),
+            sizing: SizingConfig::Span,
+            exclusive_zone: false,
+            magnification: default_magnification(),
+            magnification_range: default_magnification_range(),
+            magnify_vertical: default_magnify_vertical(),
+            previews: false,
         }
     }
 }
@@ -49,6 +193,16 @@ pub struct CalendarConfig {
     pub enabled: bool,
     pub position: Position,
     pub size: Size,
+    #[serde(default)]
+    pub output: Option<String>, // output name such as "DP-1" or "all", unset lets Sway choose
+    #[serde(default)]
+    pub click_through: bool, // pass clicks to the desktop, except on the navigation buttons
+    #[serde(default = "default_visible")]
+    pub visible: bool, // start shown, `swaydgets toggle calendar` flips it
+    #[serde(default)]
+    pub transition: TransitionConfig,
+    #[serde(default = "default_transition_duration")]
+    pub transition_duration: u64, // in milliseconds
 }
 
 // Default implementation for CalendarConfig
@@ -61,10 +215,42 @@ impl Default for CalendarConfig {
                 width: 300,
                 height: 250,
             },
+            output: None,
+            click_through: false,
+            visible: true,
+            transition: TransitionConfig::None,
+            transition_duration: default_transition_duration(),
+        }
+    }
+}
+
+// Lua script configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScriptsConfig {
+    #[serde(default = "default_fs_allow")]
+    pub fs_allow: Vec<String>, // paths scripts may access through the fs API, ~ is expanded
+    #[serde(default)]
+    pub positions: HashMap<String, Position>, // dragged windows, keyed by "script.lua/Title"
+    #[serde(default)]
+    pub hidden: Vec<String>, // widget names to start hidden
+}
+
+// Default implementation for ScriptsConfig
+impl Default for ScriptsConfig {
+    fn default() -> Self {
+        Self {
+            fs_allow: default_fs_allow(),
+            positions: HashMap::new(),
+            hidden: Vec::new(),
         }
     }
 }
 
+// Only the scripts directory, so scripts can keep their own files
+fn default_fs_allow() -> Vec<String> {
+    vec![crate::script::scripts_dir().to_string_lossy().into_owned()]
+}
+
 // Edge configuration - which edge to attach widgets to
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum EdgeConfig {
@@ -86,6 +272,36 @@ impl EdgeConfig {
     }
 }
 
+// Transition configuration - how widgets appear and disappear, spelled as
+// in Lua's set_transition
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitionConfig {
+    #[default]
+    None,
+    Fade,
+    Slide,
+}
+
+// Convert TransitionConfig to a widget transition of the given duration
+impl TransitionConfig {
+    pub fn to_transition(self, duration: u64) -> Transition {
+        match self {
+            TransitionConfig::None => Transition::None,
+            TransitionConfig::Fade => Transition::Fade(duration),
+            TransitionConfig::Slide => Transition::Slide(duration),
+        }
+    }
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_transition_duration() -> u64 {
+    200
+}
+
 // Position configuration
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub struct Position {
@@ -114,6 +330,22 @@ pub fn load_config() -> Config {
         }
     }
 
+    if let Some(config) = read_config() {
+        return config;
+    }
+
+    // If we get here, either the file doesn't exist or there was an error
+    // Create a default config and save it
+    let default_config = Config::default();
+    save_config(&default_config);
+    default_config
+}
+
+// Reads the config file without creating or rewriting it, for commands
+// that shouldn't touch the user's files
+pub fn read_config() -> Option<Config> {
+    let config_path = get_config_path();
+
     // Try to load the config file
     if config_path.exists() {
         match File::open(&config_path) {
@@ -123,7 +355,7 @@ pub fn load_config() -> Config {
                     match toml::from_str::<Config>(&contents) {
                         Ok(config) => {
                             info!("Configuration loaded from {}", config_path.display());
-                            return config;
+                            return Some(config);
                         }
                         Err(e) => {
                             error!("Failed to parse config file: {}", e);
@@ -136,12 +368,7 @@ pub fn load_config() -> Config {
             }
         }
     }
-
-    // If we get here, either the file doesn't exist or there was an error
-    // Create a default config and save it
-    let default_config = Config::default();
-    save_config(&default_config);
-    default_config
+    None
 }
 
 // Saves the configuration to the config file
@@ -179,6 +406,17 @@ pub fn save_config(config: &Config) -> bool {
     false
 }
 
+// Loads the configuration, applies a change and saves it back. A config
+// file that cannot be read is left alone rather than replaced by defaults.
+pub fn update_config(update: impl FnOnce(&mut Config)) -> bool {
+    let Some(mut config) = read_config() else {
+        error!("Not saving the change, the config file could not be read");
+        return false;
+    };
+    update(&mut config);
+    save_config(&config)
+}
+
 // Get the path to the config file
 fn get_config_path() -> PathBuf {
     let mut path = if let Some(config_dir) = dirs::config_dir() {
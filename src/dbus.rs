@@ -0,0 +1,493 @@
+use gtk::gio::{
+    self, BusType, DBusCallFlags, DBusConnection, DBusConnectionFlags, DBusSignalFlags,
+    SignalSubscriptionId,
+};
+use gtk::glib::variant::{ObjectPath, Signature};
+use gtk::glib::{ToVariant, Variant, VariantClass, VariantTy, VariantType};
+use mlua::{Function, Lua, MultiValue, Table, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::backend::Watch;
+use crate::script::ScriptContext;
+use crate::tasks::Pending;
+
+// Give up on unresponsive services, which blocking calls wait for with
+// every widget frozen
+const CALL_TIMEOUT_MS: i32 = 5000;
+
+thread_local! {
+    // Connections opened by address, shared by all scripts
+    static PRIVATE_BUSES: RefCell<HashMap<String, DBusConnection>> = RefCell::new(HashMap::new());
+}
+
+// Arguments of dbus.call: bus, destination, path, interface, method,
+// arguments and signature
+type CallArgs<'lua> = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    Option<Table<'lua>>,
+    Option<String>,
+);
+
+/// Register the `dbus` table in a script environment.
+///
+/// `bus` is `"session"`, `"system"` or a D-Bus address such as
+/// `"unix:path=/tmp/bus"`. `dbus.call(bus, dest, path, iface, method, args,
+/// signature)` returns an operation resolving to the reply, `dbus.get_property(bus,
+/// dest, path, iface, name)` one resolving to a property value, and
+/// `dbus.on_signal(bus, sender, path, iface, member, fn)` calls `fn(...)` with
+/// the arguments of matching signals, where nil filters match anything.
+/// `dbus.call_blocking` and `dbus.get_property_blocking` wait for the reply,
+/// holding up every widget meanwhile. Subscriptions end when the script is
+/// unloaded.
+pub fn register_dbus_api(lua: &Lua, env: &Table, ctx: &ScriptContext) -> Result<(), mlua::Error> {
+    let dbus = lua.create_table()?;
+
+    // dbus.call(bus, dest, path, iface, method, args, signature) calls a
+    // method. Argument types are guessed from the Lua values unless a
+    // signature such as "(su)" is given. A reply of one value resolves to
+    // that value, longer replies to a table of them.
+    {
+        let ctx = ctx.clone();
+        let call = lua.create_function(
+            move |_, (bus, dest, path, iface, method, args, signature): CallArgs| {
+                let parameters = match args {
+                    Some(args) => Some(args_to_variant(args, signature.as_deref())?),
+                    None => None,
+                };
+                let op = Pending::default();
+                let done = op.resolver(&ctx, |lua, reply: Variant| {
+                    lua.create_registry_value(reply_to_lua(lua, &reply)?)
+                });
+                call_method(&bus, &dest, &path, &iface, &method, parameters, done)?;
+                Ok(op)
+            },
+        )?;
+        dbus.set("call", call)?;
+    }
+
+    // dbus.call_blocking(...) waits for the reply and returns its values
+    let call_blocking = lua.create_function(
+        |lua, (bus, dest, path, iface, method, args, signature): CallArgs| {
+            let parameters = match args {
+                Some(args) => Some(args_to_variant(args, signature.as_deref())?),
+                None => None,
+            };
+            let reply = call_method_blocking(&bus, &dest, &path, &iface, &method, parameters)?;
+            reply
+                .iter()
+                .map(|value| variant_to_lua(lua, &value))
+                .collect::<Result<MultiValue, _>>()
+        },
+    )?;
+    dbus.set("call_blocking", call_blocking)?;
+
+    // dbus.get_property(bus, dest, path, iface, name) returns an operation
+    // resolving to a property value
+    {
+        let ctx = ctx.clone();
+        let get_property = lua.create_function(
+            move |_, (bus, dest, path, iface, name): (String, String, String, String, String)| {
+                let op = Pending::default();
+                let done = op.resolver(&ctx, |lua, reply: Variant| {
+                    lua.create_registry_value(variant_to_lua(lua, &reply.child_value(0))?)
+                });
+                call_method(
+                    &bus,
+                    &dest,
+                    &path,
+                    "org.freedesktop.DBus.Properties",
+                    "Get",
+                    Some((iface, name).to_variant()),
+                    done,
+                )?;
+                Ok(op)
+            },
+        )?;
+        dbus.set("get_property", get_property)?;
+    }
+
+    // dbus.get_property_blocking(...) waits for the property value
+    let get_property_blocking = lua.create_function(
+        |lua, (bus, dest, path, iface, name): (String, String, String, String, String)| {
+            let reply = call_method_blocking(
+                &bus,
+                &dest,
+                &path,
+                "org.freedesktop.DBus.Properties",
+                "Get",
+                Some((iface, name).to_variant()),
+            )?;
+            variant_to_lua(lua, &reply.child_value(0))
+        },
+    )?;
+    dbus.set("get_property_blocking", get_property_blocking)?;
+
+    // dbus.on_signal(bus, sender, path, iface, member, fn) subscribes to signals
+    {
+        let ctx = ctx.clone();
+        let on_signal = lua.create_function(
+            move |lua,
+                  (bus, sender, path, iface, member, func): (
+                String,
+                Option<String>,
+                Option<String>,
+                Option<String>,
+                Option<String>,
+                Function,
+            )| {
+                let connection = connect(&bus)?;
+                let key = lua.create_registry_value(func)?;
+                let callback_ctx = ctx.clone();
+                let id = connection.signal_subscribe(
+                    sender.as_deref(),
+                    iface.as_deref(),
+                    member.as_deref(),
+                    path.as_deref(),
+                    None,
+                    DBusSignalFlags::NONE,
+                    move |_, _, _, _, _, parameters| {
+                        let Some(lua) = callback_ctx.lua() else {
+                            return;
+                        };
+                        let result = parameters
+                            .iter()
+                            .map(|value| variant_to_lua(&lua, &value))
+                            .collect::<Result<MultiValue, _>>()
+                            .and_then(|args| {
+                                lua.registry_value::<Function>(&key)?.call::<_, ()>(args)
+                            });
+                        if let Err(e) = result {
+                            callback_ctx.backend().script_error(e.to_string());
+                        }
+                    },
+                );
+                ctx.keep_watch(Box::new(Subscription {
+                    connection,
+                    id: Some(id),
+                }));
+                Ok(())
+            },
+        )?;
+        dbus.set("on_signal", on_signal)?;
+    }
+
+    env.set("dbus", dbus)?;
+
+    Ok(())
+}
+
+// A signal subscription, removed when the script is unloaded
+struct Subscription {
+    connection: DBusConnection,
+    id: Option<SignalSubscriptionId>,
+}
+
+impl Watch for Subscription {}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            self.connection.signal_unsubscribe(id);
+        }
+    }
+}
+
+// Open a bus by name or address
+fn connect(bus: &str) -> Result<DBusConnection, mlua::Error> {
+    let connection = match bus {
+        "session" => gio::bus_get_sync(BusType::Session, gio::Cancellable::NONE),
+        "system" => gio::bus_get_sync(BusType::System, gio::Cancellable::NONE),
+        address => connect_address(address),
+    };
+    connection.map_err(|e| {
+        mlua::Error::RuntimeError(format!("Failed to connect to D-Bus {}: {}", bus, e))
+    })
+}
+
+// Reuse a connection to a private bus unless it has been closed
+fn connect_address(address: &str) -> Result<DBusConnection, gtk::glib::Error> {
+    PRIVATE_BUSES.with(|buses| {
+        if let Some(connection) = buses.borrow().get(address)
+            && !connection.is_closed()
+        {
+            return Ok(connection.clone());
+        }
+
+        let connection = DBusConnection::for_address_sync(
+            address,
+            DBusConnectionFlags::AUTHENTICATION_CLIENT
+                | DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            gio::Cancellable::NONE,
+        )?;
+        buses
+            .borrow_mut()
+            .insert(address.to_string(), connection.clone());
+        Ok(connection)
+    })
+}
+
+// Call a method, passing the reply tuple to `done` once it arrives
+fn call_method(
+    bus: &str,
+    dest: &str,
+    path: &str,
+    iface: &str,
+    method: &str,
+    parameters: Option<Variant>,
+    done: Box<dyn FnOnce(Result<Variant, String>)>,
+) -> Result<(), mlua::Error> {
+    let name = format!("{}.{}", iface, method);
+    connect(bus)?.call(
+        Some(dest),
+        path,
+        iface,
+        method,
+        parameters.as_ref(),
+        None,
+        DBusCallFlags::NONE,
+        CALL_TIMEOUT_MS,
+        gio::Cancellable::NONE,
+        move |reply| done(reply.map_err(|e| format!("D-Bus call {} failed: {}", name, e))),
+    );
+    Ok(())
+}
+
+// Call a method and wait for the reply tuple, blocking the main loop
+fn call_method_blocking(
+    bus: &str,
+    dest: &str,
+    path: &str,
+    iface: &str,
+    method: &str,
+    parameters: Option<Variant>,
+) -> Result<Variant, mlua::Error> {
+    connect(bus)?
+        .call_sync(
+            Some(dest),
+            path,
+            iface,
+            method,
+            parameters.as_ref(),
+            None,
+            DBusCallFlags::NONE,
+            CALL_TIMEOUT_MS,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|e| {
+            mlua::Error::RuntimeError(format!("D-Bus call {}.{} failed: {}", iface, method, e))
+        })
+}
+
+// A reply as one Lua value: nothing, its only value or a table of values
+fn reply_to_lua<'lua>(lua: &'lua Lua, reply: &Variant) -> Result<Value<'lua>, mlua::Error> {
+    match reply.n_children() {
+        0 => Ok(Value::Nil),
+        1 => variant_to_lua(lua, &reply.child_value(0)),
+        _ => variant_to_lua(lua, reply),
+    }
+}
+
+// Build the argument tuple of a call, typed by the signature if there is one
+fn args_to_variant(args: Table, signature: Option<&str>) -> Result<Variant, mlua::Error> {
+    let values = args
+        .clone()
+        .sequence_values::<Value>()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let Some(signature) = signature else {
+        let children = values
+            .iter()
+            .map(guess_variant)
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(Variant::tuple_from_iter(children));
+    };
+
+    // Accept both "su" and "(su)"
+    let signature = if signature.starts_with('(') {
+        signature.to_string()
+    } else {
+        format!("({})", signature)
+    };
+    let ty = VariantType::new(&signature).map_err(|_| {
+        mlua::Error::RuntimeError(format!("Invalid D-Bus signature: {}", signature))
+    })?;
+    lua_to_variant(&Value::Table(args), &ty)
+}
+
+// Convert a Lua value to a variant of the given type
+fn lua_to_variant(value: &Value, ty: &VariantTy) -> Result<Variant, mlua::Error> {
+    let mismatch = || {
+        mlua::Error::RuntimeError(format!(
+            "Cannot convert {} to D-Bus type {}",
+            value.type_name(),
+            ty.as_str()
+        ))
+    };
+    let integer = || match value {
+        Value::Integer(n) => Ok(*n),
+        Value::Number(n) if n.fract() == 0.0 => Ok(*n as i64),
+        _ => Err(mismatch()),
+    };
+    let string = || match value {
+        Value::String(s) => s.to_str().map(str::to_string),
+        _ => Err(mismatch()),
+    };
+
+    let variant = match ty.as_str() {
+        "b" => match value {
+            Value::Boolean(b) => b.to_variant(),
+            _ => return Err(mismatch()),
+        },
+        "y" => u8::try_from(integer()?)
+            .map_err(|_| mismatch())?
+            .to_variant(),
+        "n" => i16::try_from(integer()?)
+            .map_err(|_| mismatch())?
+            .to_variant(),
+        "q" => u16::try_from(integer()?)
+            .map_err(|_| mismatch())?
+            .to_variant(),
+        "i" => i32::try_from(integer()?)
+            .map_err(|_| mismatch())?
+            .to_variant(),
+        "u" => u32::try_from(integer()?)
+            .map_err(|_| mismatch())?
+            .to_variant(),
+        "x" => integer()?.to_variant(),
+        "t" => u64::try_from(integer()?)
+            .map_err(|_| mismatch())?
+            .to_variant(),
+        "d" => match value {
+            Value::Integer(n) => (*n as f64).to_variant(),
+            Value::Number(n) => n.to_variant(),
+            _ => return Err(mismatch()),
+        },
+        "s" => string()?.to_variant(),
+        "o" => ObjectPath::try_from(string()?)
+            .map_err(|_| mismatch())?
+            .to_variant(),
+        "g" => Signature::try_from(string()?)
+            .map_err(|_| mismatch())?
+            .to_variant(),
+        "v" => Variant::from_variant(&guess_variant(value)?),
+        _ if ty.is_maybe() => match value {
+            Value::Nil => Variant::from_none(ty.element()),
+            value => Variant::from_some(&lua_to_variant(value, ty.element())?),
+        },
+        _ if ty.is_array() => {
+            let Value::Table(table) = value else {
+                return Err(mismatch());
+            };
+            let element = ty.element();
+            let mut children = Vec::new();
+            if element.is_dict_entry() {
+                for pair in table.clone().pairs::<Value, Value>() {
+                    let (key, value) = pair?;
+                    children.push(Variant::from_dict_entry(
+                        &lua_to_variant(&key, element.key())?,
+                        &lua_to_variant(&value, element.value())?,
+                    ));
+                }
+            } else {
+                for value in table.clone().sequence_values::<Value>() {
+                    children.push(lua_to_variant(&value?, element)?);
+                }
+            }
+            Variant::array_from_iter_with_type(element, children)
+        }
+        _ if ty.is_tuple() => {
+            let Value::Table(table) = value else {
+                return Err(mismatch());
+            };
+            let mut children = Vec::new();
+            for (index, child_ty) in ty.tuple_types().enumerate() {
+                let child = table.raw_get::<_, Value>(index + 1)?;
+                children.push(lua_to_variant(&child, child_ty)?);
+            }
+            Variant::tuple_from_iter(children)
+        }
+        _ => return Err(mismatch()),
+    };
+    Ok(variant)
+}
+
+// Pick a D-Bus type for a Lua value: integers become int32 when they fit,
+// sequences arrays of variants and other tables a{sv} dictionaries
+fn guess_variant(value: &Value) -> Result<Variant, mlua::Error> {
+    let variant = match value {
+        Value::Boolean(b) => b.to_variant(),
+        Value::Integer(n) => match i32::try_from(*n) {
+            Ok(n) => n.to_variant(),
+            Err(_) => n.to_variant(),
+        },
+        Value::Number(n) => n.to_variant(),
+        Value::String(s) => s.to_str()?.to_variant(),
+        Value::Table(table) if table.raw_len() > 0 => {
+            let ty = VariantType::new("av").expect("valid type");
+            lua_to_variant(value, &ty)?
+        }
+        Value::Table(_) => {
+            let ty = VariantType::new("a{sv}").expect("valid type");
+            lua_to_variant(value, &ty)?
+        }
+        _ => {
+            return Err(mlua::Error::RuntimeError(format!(
+                "Cannot pass {} over D-Bus",
+                value.type_name()
+            )));
+        }
+    };
+    Ok(variant)
+}
+
+// Convert a variant to Lua: containers become tables, dictionaries keyed
+// tables and variants are unwrapped
+fn variant_to_lua<'lua>(lua: &'lua Lua, variant: &Variant) -> Result<Value<'lua>, mlua::Error> {
+    let value = match variant.classify() {
+        VariantClass::Boolean => Value::Boolean(variant.get::<bool>().unwrap_or_default()),
+        VariantClass::Byte => Value::Integer(variant.get::<u8>().unwrap_or_default().into()),
+        VariantClass::Int16 => Value::Integer(variant.get::<i16>().unwrap_or_default().into()),
+        VariantClass::Uint16 => Value::Integer(variant.get::<u16>().unwrap_or_default().into()),
+        VariantClass::Int32 => Value::Integer(variant.get::<i32>().unwrap_or_default().into()),
+        VariantClass::Uint32 => Value::Integer(variant.get::<u32>().unwrap_or_default().into()),
+        VariantClass::Int64 => Value::Integer(variant.get::<i64>().unwrap_or_default()),
+        VariantClass::Uint64 => Value::Integer(variant.get::<u64>().unwrap_or_default() as i64),
+        VariantClass::Double => Value::Number(variant.get::<f64>().unwrap_or_default()),
+        VariantClass::String | VariantClass::ObjectPath | VariantClass::Signature => {
+            Value::String(lua.create_string(variant.str().unwrap_or_default())?)
+        }
+        VariantClass::Variant => match variant.as_variant() {
+            Some(inner) => variant_to_lua(lua, &inner)?,
+            None => Value::Nil,
+        },
+        VariantClass::Maybe => match variant.as_maybe() {
+            Some(inner) => variant_to_lua(lua, &inner)?,
+            None => Value::Nil,
+        },
+        VariantClass::Array if variant.type_().element().is_dict_entry() => {
+            let table = lua.create_table()?;
+            for entry in variant.iter() {
+                table.set(
+                    variant_to_lua(lua, &entry.child_value(0))?,
+                    variant_to_lua(lua, &entry.child_value(1))?,
+                )?;
+            }
+            Value::Table(table)
+        }
+        VariantClass::Array | VariantClass::Tuple | VariantClass::DictEntry => {
+            let table = lua.create_table()?;
+            for child in variant.iter() {
+                table.push(variant_to_lua(lua, &child)?)?;
+            }
+            Value::Table(table)
+        }
+        _ => Value::Nil,
+    };
+    Ok(value)
+}
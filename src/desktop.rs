@@ -0,0 +1,446 @@
+use log::{debug, info, warn};
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::config::AppOverride;
+
+// Icon for apps without a desktop entry
+const FALLBACK_ICON: &str = "application-x-executable";
+
+/// How the dock names, shows and starts an app
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppInfo {
+    pub name: String,
+    /// An icon theme name, or an absolute path to an image
+    pub icon: String,
+    /// Command line with field codes expanded, None to run the app id
+    pub exec: Option<String>,
+}
+
+/// The parts of a desktop entry the dock uses
+#[derive(Debug)]
+struct DesktopEntry {
+    path: PathBuf,
+    name: String,
+    icon: Option<String>,
+    exec: Option<String>,
+    wm_class: Option<String>,
+}
+
+// Desktop entries by lowercase desktop id, short id and window class
+#[derive(Default)]
+struct DesktopIndex {
+    ids: HashMap<String, Rc<DesktopEntry>>,
+    short_ids: HashMap<String, Rc<DesktopEntry>>,
+    wm_classes: HashMap<String, Rc<DesktopEntry>>,
+}
+
+thread_local! {
+    // Built on first lookup
+    static INDEX: OnceCell<DesktopIndex> = const { OnceCell::new() };
+
+    // Per-app settings from the config, keyed by lowercase app id
+    static OVERRIDES: RefCell<HashMap<String, AppOverride>> = RefCell::new(HashMap::new());
+}
+
+/// Use the user's per-app settings, which win over desktop entries
+pub fn set_overrides(overrides: &HashMap<String, AppOverride>) {
+    OVERRIDES.with(|current| {
+        *current.borrow_mut() = overrides
+            .iter()
+            .map(|(app_id, settings)| (app_id.to_lowercase(), settings.clone()))
+            .collect()
+    });
+}
+
+/// Look up the name, icon and command of the app with this Wayland app id or
+/// X11 class
+pub fn app_info(app_id: &str) -> AppInfo {
+    let key = app_id.to_lowercase();
+    let settings = OVERRIDES.with(|overrides| overrides.borrow().get(&key).cloned());
+    let settings = settings.unwrap_or_default();
+
+    INDEX.with(|index| {
+        let entry = index.get_or_init(DesktopIndex::load).find(&key);
+        AppInfo {
+            name: settings
+                .name
+                .or_else(|| entry.map(|entry| entry.name.clone()))
+                .unwrap_or_else(|| app_id.to_string()),
+            icon: settings
+                .icon
+                .or_else(|| entry.and_then(|entry| entry.icon.clone()))
+                .unwrap_or_else(|| FALLBACK_ICON.to_string()),
+            exec: match (settings.exec, entry) {
+                (Some(exec), _) => Some(exec),
+                (None, Some(entry)) => entry.exec.as_ref().map(|exec| expand_exec(exec, entry)),
+                (None, None) => None,
+            },
+        }
+    })
+}
+
+impl DesktopIndex {
+    // Index the applications directories, earlier ones taking precedence
+    fn load() -> Self {
+        let mut index = DesktopIndex::default();
+        let locales = locale_names();
+        for dir in application_dirs() {
+            index.add_dir(&dir, &dir, &locales);
+        }
+        info!("Indexed {} desktop entries", index.ids.len());
+        index
+    }
+
+    fn add_dir(&mut self, root: &Path, dir: &Path, locales: &[String]) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                self.add_dir(root, &path, locales);
+                continue;
+            }
+            if path.extension().is_none_or(|ext| ext != "desktop") {
+                continue;
+            }
+
+            // Entries in subdirectories have ids like kde-dolphin
+            let Some(id) = path
+                .strip_prefix(root)
+                .ok()
+                .and_then(|relative| relative.with_extension("").to_str().map(str::to_string))
+                .map(|relative| relative.replace('/', "-").to_lowercase())
+            else {
+                continue;
+            };
+            if self.ids.contains_key(&id) {
+                continue;
+            }
+
+            match std::fs::read_to_string(&path) {
+                Ok(contents) => {
+                    if let Some(entry) = parse_entry(&path, &contents, locales) {
+                        self.add(id, Rc::new(entry));
+                    }
+                }
+                Err(e) => warn!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    fn add(&mut self, id: String, entry: Rc<DesktopEntry>) {
+        // Reverse-DNS ids such as org.gnome.Nautilus also match "nautilus"
+        if let Some((_, short)) = id.rsplit_once('.') {
+            self.short_ids
+                .entry(short.to_string())
+                .or_insert_with(|| entry.clone());
+        }
+        if let Some(wm_class) = &entry.wm_class {
+            self.wm_classes
+                .entry(wm_class.to_lowercase())
+                .or_insert_with(|| entry.clone());
+        }
+        self.ids.insert(id, entry);
+    }
+
+    fn find(&self, key: &str) -> Option<&DesktopEntry> {
+        let short = key.rsplit_once('.').map(|(_, short)| short);
+        self.ids
+            .get(key)
+            .or_else(|| self.wm_classes.get(key))
+            .or_else(|| self.short_ids.get(key))
+            .or_else(|| short.and_then(|short| self.ids.get(short)))
+            .map(Rc::as_ref)
+    }
+}
+
+// The applications directories from the XDG base directory spec, most
+// important first
+fn application_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+// Locale suffixes to try for Name[...] in the user's locale
+fn locale_names() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    locale_suffixes(&locale)
+}
+
+// Locale suffixes to try for Name[...], best match first. For de_DE.UTF-8@euro
+// these are de_DE@euro, de_DE, de@euro and de.
+fn locale_suffixes(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut names = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        names.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        names.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        names.push(format!("{}@{}", lang, modifier));
+    }
+    names.push(lang.to_string());
+    names
+}
+
+// Read the [Desktop Entry] group, skipping entries that are not shown
+fn parse_entry(path: &Path, contents: &str, locales: &[String]) -> Option<DesktopEntry> {
+    let mut in_entry = false;
+    let mut values: HashMap<&str, String> = HashMap::new();
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_entry {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            values.insert(key.trim(), unescape(value.trim()));
+        }
+    }
+
+    if values.get("Type").is_some_and(|kind| kind != "Application")
+        || values.get("Hidden").is_some_and(|hidden| hidden == "true")
+    {
+        debug!("Skipping desktop entry {}", path.display());
+        return None;
+    }
+
+    let name = locales
+        .iter()
+        .find_map(|locale| values.get(format!("Name[{}]", locale).as_str()))
+        .or_else(|| values.get("Name"))?
+        .clone();
+    Some(DesktopEntry {
+        path: path.to_path_buf(),
+        name,
+        icon: values.remove("Icon").filter(|icon| !icon.is_empty()),
+        exec: values.remove("Exec").filter(|exec| !exec.is_empty()),
+        wm_class: values.remove("StartupWMClass"),
+    })
+}
+
+// Undo the escapes allowed in desktop entry values
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some(other) => {
+                // Other escapes belong to the Exec quoting rules
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+// Expand the field codes of an Exec line for a launch without files. File
+// and URL codes are dropped, %i, %c and %k become the icon, name and entry
+// path.
+fn expand_exec(exec: &str, entry: &DesktopEntry) -> String {
+    let mut result = String::with_capacity(exec.len());
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => result.push('%'),
+            Some('i') => {
+                if let Some(icon) = &entry.icon {
+                    result.push_str("--icon ");
+                    result.push_str(&shell_quote(icon));
+                }
+            }
+            Some('c') => result.push_str(&shell_quote(&entry.name)),
+            Some('k') => result.push_str(&shell_quote(&entry.path.to_string_lossy())),
+            _ => {}
+        }
+    }
+    result.trim().to_string()
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(contents: &str) -> Option<DesktopEntry> {
+        parse_entry(Path::new("/apps/test.desktop"), contents, &[])
+    }
+
+    fn entry_with(name: &str, wm_class: Option<&str>) -> Rc<DesktopEntry> {
+        Rc::new(DesktopEntry {
+            path: PathBuf::from(format!("/apps/{}.desktop", name)),
+            name: name.to_string(),
+            icon: None,
+            exec: None,
+            wm_class: wm_class.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn locale_suffixes_fall_back_in_order() {
+        assert_eq!(
+            locale_suffixes("de_DE.UTF-8@euro"),
+            ["de_DE@euro", "de_DE", "de@euro", "de"]
+        );
+        assert_eq!(locale_suffixes("pt_BR.UTF-8"), ["pt_BR", "pt"]);
+        assert_eq!(locale_suffixes("sr@latin"), ["sr@latin", "sr"]);
+        assert!(locale_suffixes("C.UTF-8").is_empty());
+        assert!(locale_suffixes("").is_empty());
+    }
+
+    #[test]
+    fn localized_name_uses_best_locale() {
+        let contents = "[Desktop Entry]\n\
+                        Type=Application\n\
+                        Name=Files\n\
+                        Name[de]=Dateien\n\
+                        Name[de_DE]=Dateien (DE)\n";
+        let name = |locale: &str| {
+            parse_entry(
+                Path::new("/apps/files.desktop"),
+                contents,
+                &locale_suffixes(locale),
+            )
+            .map(|entry| entry.name)
+        };
+        assert_eq!(name("de_DE.UTF-8@euro").as_deref(), Some("Dateien (DE)"));
+        assert_eq!(name("de_AT.UTF-8").as_deref(), Some("Dateien"));
+        assert_eq!(name("fr_FR.UTF-8").as_deref(), Some("Files"));
+    }
+
+    #[test]
+    fn entries_read_only_the_desktop_entry_group() {
+        let entry = entry(
+            "# comment\n\
+             [Desktop Entry]\n\
+             Type=Application\n\
+             Name=Editor\n\
+             Icon=editor\n\
+             Exec=editor\\sfile\n\
+             StartupWMClass=Editor-Main\n\
+             [Desktop Action new]\n\
+             Name=New Window\n\
+             Exec=editor --new\n",
+        )
+        .expect("entry is shown");
+        assert_eq!(entry.name, "Editor");
+        assert_eq!(entry.icon.as_deref(), Some("editor"));
+        assert_eq!(entry.exec.as_deref(), Some("editor file"));
+        assert_eq!(entry.wm_class.as_deref(), Some("Editor-Main"));
+    }
+
+    #[test]
+    fn hidden_and_non_application_entries_are_skipped() {
+        assert!(entry("[Desktop Entry]\nType=Application\nName=Gone\nHidden=true\n").is_none());
+        assert!(entry("[Desktop Entry]\nType=Link\nName=Site\n").is_none());
+        assert!(entry("[Desktop Entry]\nType=Directory\nName=Games\n").is_none());
+        assert!(entry("[Desktop Entry]\nType=Application\nName=Shown\n").is_some());
+        assert!(entry("[Desktop Entry]\nType=Application\n").is_none());
+    }
+
+    #[test]
+    fn exec_expands_field_codes() {
+        let entry = DesktopEntry {
+            path: PathBuf::from("/apps/it's.desktop"),
+            name: "My App".to_string(),
+            icon: Some("my-app".to_string()),
+            exec: None,
+            wm_class: None,
+        };
+        assert_eq!(expand_exec("app --rate 100%%", &entry), "app --rate 100%");
+        assert_eq!(expand_exec("app %i", &entry), "app --icon 'my-app'");
+        assert_eq!(
+            expand_exec("app --title %c", &entry),
+            "app --title 'My App'"
+        );
+        assert_eq!(
+            expand_exec("app --entry %k", &entry),
+            r"app --entry '/apps/it'\''s.desktop'"
+        );
+        assert_eq!(expand_exec("app %U", &entry), "app");
+        assert_eq!(expand_exec("app %f --new", &entry), "app  --new");
+
+        let no_icon = DesktopEntry {
+            icon: None,
+            ..entry
+        };
+        assert_eq!(expand_exec("app %i", &no_icon), "app");
+    }
+
+    #[test]
+    fn find_matches_ids_classes_and_short_ids() {
+        let mut index = DesktopIndex::default();
+        index.add("org.gnome.nautilus".to_string(), entry_with("Files", None));
+        index.add(
+            "code".to_string(),
+            entry_with("Code", Some("Code-Insiders")),
+        );
+        index.add("firefox".to_string(), entry_with("Firefox", None));
+
+        let name = |key: &str| index.find(key).map(|entry| entry.name.clone());
+        assert_eq!(name("org.gnome.nautilus").as_deref(), Some("Files"));
+        // Windows reporting the short name find the reverse-DNS entry
+        assert_eq!(name("nautilus").as_deref(), Some("Files"));
+        // And reverse-DNS app ids find plain entries
+        assert_eq!(name("org.mozilla.firefox").as_deref(), Some("Firefox"));
+        // Window classes are matched in lowercase
+        assert_eq!(name("code-insiders").as_deref(), Some("Code"));
+        assert_eq!(name("unknown"), None);
+    }
+}
@@ -1,71 +1,336 @@
-use glib;
-use gtk::gdk::NotifyType;
+use gtk::gdk::{self, NotifyType};
+use gtk::gdk_pixbuf::Pixbuf;
 use gtk::pango;
 use gtk::prelude::*;
 use gtk::{Application, ApplicationWindow, Box as GtkBox, Button, Image, Label, Orientation};
-use gtk_layer_shell::{Edge, Layer, LayerShell};
-use log::info;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use gtk::{gio, glib};
+use gtk_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
+use log::{error, info, warn};
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+use std::rc::Rc;
 use std::time::Duration;
-use swayipc::{Connection, EventType, Node};
+use swayipc::{Event, Node, NodeType, WindowChange, Workspace};
 
-use crate::config::{DockConfig, EdgeConfig};
+use crate::config::{
+    self, AutohideConfig, DockConfig, DockFilter, EdgeConfig, LabelConfig, SizingConfig,
+};
+use crate::preview::{PREVIEW_WIDTH, PreviewTarget, Previews};
+use crate::sway::{self, Subscription, SwayEvent};
+use crate::visibility::{self, Toggle};
+use crate::{desktop, outputs};
 
-// Create a dock attached to the given application
-pub fn create_dock(app: &Application, config: &DockConfig) {
-    // Store window info in thread-safe container
-    let windows = Arc::new(Mutex::new(Vec::new()));
-    let windows_clone = Arc::clone(&windows);
-
-    // Spawn thread to listen for Sway window events
-    thread::spawn(move || {
-        let mut connection = Connection::new().expect("Failed to connect to Sway");
-        update_window_list(&mut connection, &windows_clone);
-
-        // Subscribe to window events
-        match connection.subscribe(&[EventType::Window]) {
-            Ok(events) => {
-                for event in events {
-                    if event.is_ok() {
-                        // Update window list when any window event occurs
-                        let mut conn = Connection::new().expect("Failed to connect to Sway");
-                        update_window_list(&mut conn, &windows_clone);
-                    }
+// Name used to show and hide the dock
+const NAME: &str = "dock";
+
+// Drag target for window buttons, carrying the window's con_id
+const WINDOW_TARGET: &str = "application/x-swaydgets-window";
+
+// How long the pointer rests on a button before its preview shows, and how
+// long a newly focused window gets to draw before it is captured
+const PREVIEW_DELAY: Duration = Duration::from_millis(500);
+const PREVIEW_SETTLE: Duration = Duration::from_millis(300);
+
+// Size of the app icon standing in for a window that has no picture
+const PREVIEW_ICON_SIZE: i32 = 96;
+
+// A change to the Sway windows
+#[derive(Debug)]
+enum DockEvent {
+    // The full window list, taken at startup and when Sway comes back
+    Snapshot(Vec<WindowInfo>),
+    Close(i64),
+    Focus(i64),
+    Title { id: i64, title: String },
+    Urgent { id: i64, urgent: bool },
+    Floating { id: i64, floating: bool },
+    Fullscreen { id: i64, fullscreen: bool },
+}
+
+impl DockEvent {
+    // Translate a Sway window event, skipping changes the dock does not show
+    fn from_sway(change: WindowChange, container: &Node) -> Option<DockEvent> {
+        let id = container.id;
+        let event = match change {
+            // Events do not say which workspace a window is on, so new and
+            // moved windows are placed by reading the tree again
+            WindowChange::New | WindowChange::Move => DockEvent::Snapshot(snapshot_windows()),
+            WindowChange::Close => DockEvent::Close(id),
+            WindowChange::Focus => DockEvent::Focus(id),
+            WindowChange::Title => DockEvent::Title {
+                id,
+                title: container.name.clone().unwrap_or_default(),
+            },
+            WindowChange::Urgent => DockEvent::Urgent {
+                id,
+                urgent: container.urgent,
+            },
+            WindowChange::Floating => DockEvent::Floating {
+                id,
+                floating: container.node_type == NodeType::FloatingCon,
+            },
+            WindowChange::FullscreenMode => DockEvent::Fullscreen {
+                id,
+                fullscreen: container.fullscreen_mode.is_some_and(|mode| mode > 0),
+            },
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    // Apply the change to the window list
+    fn apply(self, windows: &mut Vec<WindowInfo>) {
+        match self {
+            DockEvent::Snapshot(list) => *windows = list,
+            DockEvent::Close(id) => windows.retain(|window| window.id != id),
+            DockEvent::Focus(id) => {
+                for window in windows.iter_mut() {
+                    window.focused = window.id == id;
+                }
+            }
+            DockEvent::Title { id, title } => {
+                if let Some(window) = windows.iter_mut().find(|w| w.id == id) {
+                    window.title = title;
+                }
+            }
+            DockEvent::Urgent { id, urgent } => {
+                if let Some(window) = windows.iter_mut().find(|w| w.id == id) {
+                    window.urgent = urgent;
+                }
+            }
+            DockEvent::Floating { id, floating } => {
+                if let Some(window) = windows.iter_mut().find(|w| w.id == id) {
+                    window.floating = floating;
+                }
+            }
+            DockEvent::Fullscreen { id, fullscreen } => {
+                if let Some(window) = windows.iter_mut().find(|w| w.id == id) {
+                    window.fullscreen = fullscreen;
                 }
             }
-            Err(e) => eprintln!("Failed to subscribe to events: {}", e),
         }
-    });
+    }
+}
 
-    // Determine orientation based on edge
-    let orientation = match config.edge {
-        EdgeConfig::Left | EdgeConfig::Right => Orientation::Vertical,
-        EdgeConfig::Top | EdgeConfig::Bottom => Orientation::Horizontal,
+// The Sway windows and the dock instances showing them, one per output
+#[derive(Default)]
+struct DockState {
+    windows: RefCell<Vec<WindowInfo>>,
+    workspaces: RefCell<Vec<Workspace>>,
+    pinned: RefCell<Vec<String>>,
+    group: bool,
+    filter: DockFilter,
+    // Whether docks show the workspace switcher
+    workspace_strip: bool,
+    autohide: AutohideConfig,
+    timing: Timing,
+    style: DockStyle,
+    exclusive_zone: bool,
+    // Window pictures for hover popovers, when turned on
+    previews: Option<Rc<Previews>>,
+    views: RefCell<Vec<Rc<DockView>>>,
+    autohides: RefCell<Vec<Rc<Autohide>>>,
+    subscription: RefCell<Option<Subscription>>,
+}
+
+impl DockState {
+    // Follow a Sway event, starting over from a fresh snapshot on reconnection
+    fn handle(&self, event: &SwayEvent) {
+        let event = match event {
+            SwayEvent::Connected => {
+                *self.workspaces.borrow_mut() = snapshot_workspaces();
+                DockEvent::Snapshot(snapshot_windows())
+            }
+            SwayEvent::Event(Event::Workspace(_)) => {
+                *self.workspaces.borrow_mut() = snapshot_workspaces();
+                if self.autohide != AutohideConfig::Intelligent {
+                    self.refresh();
+                    return;
+                }
+                DockEvent::Snapshot(snapshot_windows())
+            }
+            // Window events do not say how the other windows were resized, so
+            // intellihide reads every window's geometry again, unless the
+            // change leaves the layout alone
+            SwayEvent::Event(Event::Window(event))
+                if self.autohide == AutohideConfig::Intelligent
+                    && !matches!(
+                        event.change,
+                        WindowChange::Title | WindowChange::Mark | WindowChange::Urgent
+                    ) =>
+            {
+                DockEvent::Snapshot(snapshot_windows())
+            }
+            SwayEvent::Event(Event::Window(event)) => {
+                match DockEvent::from_sway(event.change, &event.container) {
+                    Some(event) => event,
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+        let focused = match event {
+            DockEvent::Focus(id) => Some(id),
+            _ => None,
+        };
+        self.apply(event);
+
+        // A newly focused window is on top, so its picture is worth taking
+        // once it has been drawn
+        if let (Some(id), Some(previews)) = (focused, &self.previews) {
+            let previews = Rc::downgrade(previews);
+            glib::timeout_add_local_once(PREVIEW_SETTLE, move || {
+                if let Some(previews) = previews.upgrade() {
+                    previews.refresh(id, |_| {});
+                }
+            });
+        }
+    }
+
+    // Apply a change and show it on every dock instance
+    fn apply(&self, event: DockEvent) {
+        event.apply(&mut self.windows.borrow_mut());
+        self.refresh();
+    }
+
+    // The items for the dock on `output`, None for a dock placed by Sway
+    fn items(&self, output: Option<&str>) -> Vec<DockItem> {
+        let visible_workspaces = visible_names(&self.workspaces.borrow());
+        let windows: Vec<WindowInfo> = self
+            .windows
+            .borrow()
+            .iter()
+            .filter(|window| window.shown_on(self.filter, output, &visible_workspaces))
+            .cloned()
+            .collect();
+        dock_items(
+            &windows,
+            &self.pinned.borrow(),
+            self.group,
+            &visible_workspaces,
+        )
+    }
+
+    fn refresh(&self) {
+        let workspaces = self.workspaces.borrow();
+        for view in self.views.borrow().iter() {
+            view.sync(&self.items(view.output.as_deref()));
+            view.sync_workspaces(&workspaces);
+        }
+        let windows = self.windows.borrow();
+        let visible_workspaces = visible_names(&workspaces);
+        for autohide in self.autohides.borrow().iter() {
+            autohide.check_windows(&windows, &visible_workspaces);
+        }
+        if let Some(previews) = &self.previews {
+            previews.set_targets(preview_targets(&windows, &visible_workspaces));
+        }
+    }
+
+    // Pin or unpin an app and save the list to the config
+    fn set_pinned(&self, app_id: &str, pinned: bool) {
+        {
+            let mut list = self.pinned.borrow_mut();
+            let position = list.iter().position(|app| app.eq_ignore_ascii_case(app_id));
+            match (pinned, position) {
+                (true, None) => list.push(app_id.to_string()),
+                (false, Some(index)) => {
+                    list.remove(index);
+                }
+                _ => return,
+            }
+        }
+
+        info!("{} {}", if pinned { "Pinned" } else { "Unpinned" }, app_id);
+        let list = self.pinned.borrow().clone();
+        config::update_config(|config| config.dock.pinned = list);
+        self.refresh();
+    }
+}
+
+// Create a dock attached to the given application
+pub fn create_dock(app: &Application, config: &DockConfig) {
+    let state = Rc::new(DockState {
+        workspaces: RefCell::new(snapshot_workspaces()),
+        pinned: RefCell::new(config.pinned.clone()),
+        group: config.group_windows,
+        filter: config.filter,
+        workspace_strip: config.workspaces,
+        autohide: config.autohide,
+        timing: Timing {
+            hide_delay: Duration::from_millis(config.hide_timeout),
+            reveal_delay: Duration::from_millis(config.reveal_delay),
+            reveal_travel: f64::from(config.reveal_travel),
+        },
+        style: DockStyle::new(config),
+        exclusive_zone: config.exclusive_zone,
+        previews: config.previews.then(Previews::with_default_sources),
+        ..DockState::default()
+    });
+    if config.exclusive_zone && config.autohide != AutohideConfig::Never {
+        warn!("The dock only reserves an exclusive zone with autohide = \"never\"");
+    }
+    desktop::set_overrides(&config.apps);
+    state.apply(DockEvent::Snapshot(snapshot_windows()));
+    // The subscription keeps the state alive for as long as the app runs,
+    // the dock windows only hold on to it weakly
+    let subscription = {
+        let state = state.clone();
+        sway::subscribe(move |event| state.handle(event))
     };
+    *state.subscription.borrow_mut() = Some(subscription);
 
-    // Determine dimensions based on orientation
-    let (width, height) = match orientation {
-        Orientation::Horizontal => (800, 60),
-        Orientation::Vertical => (60, 800),
-        _ => (800, 60),
+    let app = app.clone();
+    let edge = config.edge;
+    // Filtering and hiding by what is on an output need a dock on every
+    // output that knows which one it is on
+    let per_output = config.filter != DockFilter::All
+        || matches!(
+            config.autohide,
+            AutohideConfig::Intelligent | AutohideConfig::Fullscreen
+        );
+    let output = match &config.output {
+        None if per_output => Some(outputs::ALL_OUTPUTS),
+        output => output.as_deref(),
     };
+    outputs::instantiate(output, move |monitor, output| {
+        build_dock_windows(&app, monitor, output, edge, &state)
+    });
+}
+
+// Create the dock and its edge detector on one monitor
+fn build_dock_windows(
+    app: &Application,
+    monitor: Option<&gdk::Monitor>,
+    output: Option<&str>,
+    edge: EdgeConfig,
+    state: &Rc<DockState>,
+) -> Vec<gtk::Window> {
+    let orientation = dock_orientation(edge);
+    let (width, height) = dock_size(orientation, &state.style);
+    let span = state.style.sizing == SizingConfig::Span;
 
-    // Create main dock window
+    // Create main dock window, which fits its buttons unless it spans the edge
     let dock_window = ApplicationWindow::builder()
         .application(app)
         .title("Sway Dock")
-        .default_width(width)
-        .default_height(height)
         .build();
+    if span {
+        dock_window.set_default_size(width, height);
+    }
 
     // Set up layer shell
     dock_window.init_layer_shell();
+    outputs::place(&dock_window, monitor);
     dock_window.set_layer(Layer::Top);
-    dock_window.set_anchor(config.edge.to_edge(), true);
+    dock_window.set_anchor(edge.to_edge(), true);
+    // Take the keyboard once clicked, so arrow keys move between buttons
+    // and Enter focuses the selected window
+    dock_window.set_keyboard_mode(KeyboardMode::OnDemand);
 
     // Set anchors for top/bottom edges
-    match config.edge {
+    match edge {
+        _ if !span => {}
         EdgeConfig::Top | EdgeConfig::Bottom => {
             dock_window.set_anchor(Edge::Left, true);
             dock_window.set_anchor(Edge::Right, true);
@@ -75,6 +340,10 @@ pub fn create_dock(app: &Application, config: &DockConfig) {
             dock_window.set_anchor(Edge::Bottom, true);
         }
     }
+    // A dock that stays up can keep windows from going under it
+    if state.exclusive_zone && state.autohide == AutohideConfig::Never {
+        dock_window.auto_exclusive_zone_enable();
+    }
 
     // Make window transparent
     dock_window.set_app_paintable(true);
@@ -84,10 +353,7 @@ pub fn create_dock(app: &Application, config: &DockConfig) {
         false.into()
     });
 
-    // Create dock container with configured orientation
-    let dock_box = GtkBox::new(orientation, 5);
-    dock_box.set_halign(gtk::Align::Center);
-    dock_box.set_margin(5);
+    let dock_box = build_dock_box(orientation, &state.style);
 
     // Create detection area (small strip at the configured edge)
     let detection_window = ApplicationWindow::builder()
@@ -100,10 +366,11 @@ pub fn create_dock(app: &Application, config: &DockConfig) {
 
     // Set up layer shell for detection window
     detection_window.init_layer_shell();
+    outputs::place(&detection_window, monitor);
     detection_window.set_layer(Layer::Overlay);
-    detection_window.set_anchor(config.edge.to_edge(), true);
+    detection_window.set_anchor(edge.to_edge(), true);
 
-    match config.edge {
+    match edge {
         EdgeConfig::Top | EdgeConfig::Bottom => {
             detection_window.set_anchor(Edge::Left, true);
             detection_window.set_anchor(Edge::Right, true);
@@ -114,22 +381,123 @@ pub fn create_dock(app: &Application, config: &DockConfig) {
         }
     }
 
-    // Hide dock initially
-    dock_window.hide();
+    // Follow window changes until this instance is closed
+    let on_pin = {
+        let state = Rc::downgrade(state);
+        Rc::new(move |app_id: &str, pinned: bool| {
+            if let Some(state) = state.upgrade() {
+                state.set_pinned(app_id, pinned);
+            }
+        })
+    };
+    let output = output.map(str::to_string);
+    let mut view =
+        DockView::new(&dock_box, orientation, state.style, on_pin).on_output(output.clone());
+    let content = if state.workspace_strip {
+        // The switcher goes before the window buttons, in a box of its own
+        // so the buttons keep their positions in the dock box
+        let strip = WorkspaceStrip::new(orientation);
+        let content = GtkBox::new(orientation, 5);
+        content.set_halign(gtk::Align::Center);
+        content.pack_start(&strip.strip_box, false, false, 0);
+        content.pack_start(&dock_box, false, false, 0);
+        view = view.with_workspaces(strip);
+        content
+    } else {
+        dock_box
+    };
+    if let Some(previews) = &state.previews {
+        view = view.with_previews(previews.clone());
+    }
+    let view = Rc::new(view);
+    view.sync(&state.items(view.output.as_deref()));
+    view.sync_workspaces(&state.workspaces.borrow());
+    state.views.borrow_mut().push(view.clone());
 
-    // Show dock when mouse enters detector
-    let dock_window_clone = dock_window.clone();
-    detection_window.connect_enter_notify_event(move |_, _| {
-        info!("Mouse entered dock detector");
-        dock_window_clone.show_all();
-        false.into()
+    // Zoom the icons near the pointer
+    if state.style.magnifies(orientation) {
+        dock_window.add_events(gdk::EventMask::POINTER_MOTION_MASK);
+        let view = view.clone();
+        dock_window.connect_motion_notify_event(move |dock_window, event| {
+            // The event comes from whichever GDK window is under the
+            // pointer, such as a button's input window, so ask for the
+            // position on the dock window itself
+            let (Some(window), Some(device)) = (dock_window.window(), event.device()) else {
+                return glib::Propagation::Proceed;
+            };
+            let (_, x, y, _) = window.device_position(&device);
+            let (x, y) = (f64::from(x), f64::from(y));
+            view.magnify(Some(match orientation {
+                Orientation::Vertical => y,
+                _ => x,
+            }));
+            glib::Propagation::Proceed
+        });
+    }
+    dock_window.add(&content);
+
+    // Hide dock initially, unless the mode keeps it up
+    dock_window.hide();
+    let autohide = Rc::new(Autohide {
+        mode: state.autohide,
+        timing: state.timing,
+        window: dock_window.clone(),
+        detector: detection_window.clone(),
+        content: content.clone(),
+        edge,
+        monitor: monitor.cloned(),
+        output,
+        enabled: Cell::new(visibility::is_visible(NAME)),
+        hovered: Cell::new(false),
+        clear: Cell::new(true),
+        hide_timer: RefCell::new(None),
+        reveal_timer: RefCell::new(None),
+        travel: Cell::new(None),
     });
+    autohide.check_windows(
+        &state.windows.borrow(),
+        &visible_names(&state.workspaces.borrow()),
+    );
+    state.autohides.borrow_mut().push(autohide.clone());
+
+    // Show dock when the pointer rests on or moves along the detector
+    detection_window.add_events(gdk::EventMask::POINTER_MOTION_MASK);
+    {
+        let autohide = autohide.clone();
+        detection_window.connect_enter_notify_event(move |_, event| {
+            info!("Mouse entered dock detector");
+            autohide.enter_strip(event.position());
+            false.into()
+        });
+    }
+    {
+        let autohide = autohide.clone();
+        detection_window.connect_motion_notify_event(move |_, event| {
+            autohide.move_on_strip(event.position());
+            false.into()
+        });
+    }
+    {
+        let autohide = autohide.clone();
+        detection_window.connect_leave_notify_event(move |_, _| {
+            autohide.leave_strip();
+            false.into()
+        });
+    }
 
-    // Use configured hide_timeout
-    let hide_delay = config.hide_timeout;
+    // Keep the dock up while the pointer is back on it
+    {
+        let autohide = autohide.clone();
+        dock_window.connect_enter_notify_event(move |_, _| {
+            autohide.cancel_hide();
+            false.into()
+        });
+    }
 
     // Hide dock when mouse completely leaves it (not when it moves between children)
-    dock_window.connect_leave_notify_event(move |window, event| {
+    let hover = autohide.clone();
+    let magnified = view.clone();
+    dock_window.connect_leave_notify_event(move |_, event| {
         // Get the crossing detail - this tells us where the pointer went
         let detail = event.detail();
 
@@ -143,25 +511,148 @@ pub fn create_dock(app: &Application, config: &DockConfig) {
                 "Mouse truly left dock, scheduling close (detail: {:?})",
                 detail
             );
-            let window_clone = window.clone();
-            glib::timeout_add_local(Duration::from_millis(hide_delay), move || {
-                window_clone.hide();
-                false.into()
-            });
+            hover.schedule_hide();
+            magnified.magnify(None);
         }
 
         false.into()
     });
 
-    // Update dock contents periodically
-    let windows_ref = Arc::clone(&windows);
-    let dock_box_ref = dock_box.clone();
-    let orientation = orientation.clone();
-    glib::timeout_add_local(Duration::from_millis(1000), move || {
-        update_dock_ui(&dock_box_ref, &windows_ref, orientation);
-        true.into()
+    // Escape gives the keyboard back by closing the dock
+    {
+        let autohide = autohide.clone();
+        dock_window.connect_key_press_event(move |_, event| {
+            if event.keyval() == gdk::keys::constants::Escape {
+                autohide.set_hovered(false);
+                return glib::Propagation::Stop;
+            }
+            glib::Propagation::Proceed
+        });
+    }
+
+    // Scrolling over the dock cycles focus through all of its windows
+    dock_window.add_events(gdk::EventMask::SCROLL_MASK);
+    {
+        let view = view.clone();
+        dock_window.connect_scroll_event(move |_, event| {
+            if let Some(forward) = scroll_forward(event) {
+                view.cycle(forward);
+            }
+            glib::Propagation::Stop
+        });
+    }
+
+    autohide.show_detector();
+
+    // Reachable by name until the window goes away with its output
+    let registration = RefCell::new(Some(visibility::register(NAME, autohide.clone())));
+    let state = Rc::downgrade(state);
+    dock_window.connect_destroy(move |_| {
+        drop(registration.take());
+        if let Some(state) = state.upgrade() {
+            state
+                .views
+                .borrow_mut()
+                .retain(|other| !Rc::ptr_eq(other, &view));
+            state
+                .autohides
+                .borrow_mut()
+                .retain(|other| !Rc::ptr_eq(other, &autohide));
+        }
     });
 
+    vec![dock_window.upcast(), detection_window.upcast()]
+}
+
+// Determine orientation based on edge
+pub fn dock_orientation(edge: EdgeConfig) -> Orientation {
+    match edge {
+        EdgeConfig::Left | EdgeConfig::Right => Orientation::Vertical,
+        EdgeConfig::Top | EdgeConfig::Bottom => Orientation::Horizontal,
+    }
+}
+
+/// How dock buttons are sized and labelled
+#[derive(Clone, Copy, Debug)]
+pub struct DockStyle {
+    icon_size: i32,
+    spacing: i32,
+    padding: i32,
+    labels: LabelConfig,
+    label_width: i32,
+    sizing: SizingConfig,
+    // Largest icon scale, and how far from the pointer icons grow
+    magnification: f64,
+    magnification_range: f64,
+    magnify_vertical: bool,
+}
+
+impl DockStyle {
+    pub fn new(config: &DockConfig) -> Self {
+        DockStyle {
+            icon_size: config.icon_size.max(1),
+            spacing: config.spacing.max(0),
+            padding: config.padding.max(0),
+            labels: config.labels,
+            label_width: config.label_width.max(1),
+            sizing: config.sizing,
+            magnification: config.magnification.max(1.0),
+            magnification_range: f64::from(config.magnification_range.max(1)),
+            magnify_vertical: config.magnify_vertical,
+        }
+    }
+
+    fn magnifies(&self, orientation: Orientation) -> bool {
+        self.magnification > 1.0
+            && (orientation == Orientation::Horizontal || self.magnify_vertical)
+    }
+
+    // Parabolic zoom: full size under the pointer, falling to none at the
+    // edge of the range
+    fn scale_at(&self, distance: f64) -> f64 {
+        let reach = 1.0 - (distance / self.magnification_range).powi(2);
+        1.0 + (self.magnification - 1.0) * reach.max(0.0)
+    }
+
+    fn pixel_size(&self, scale: f64) -> i32 {
+        (f64::from(self.icon_size) * scale).round() as i32
+    }
+}
+
+impl Default for DockStyle {
+    fn default() -> Self {
+        DockStyle::new(&DockConfig::default())
+    }
+}
+
+// Determine dimensions based on orientation: the length of the edge to
+// cover and the thickness the buttons need
+pub fn dock_size(orientation: Orientation, style: &DockStyle) -> (i32, i32) {
+    // Button padding and border, plus a line of text when labels show
+    let label = if style.labels == LabelConfig::Below {
+        16
+    } else {
+        0
+    };
+    // Leave room for magnified icons so the window need not grow
+    let icon = if style.magnifies(orientation) {
+        style.pixel_size(style.magnification)
+    } else {
+        style.icon_size
+    };
+    let thickness = icon + 2 * style.padding + 12;
+    match orientation {
+        Orientation::Vertical => (thickness, 800),
+        _ => (800, thickness + label),
+    }
+}
+
+// Create dock container with configured orientation and load the dock styling
+pub fn build_dock_box(orientation: Orientation, style: &DockStyle) -> GtkBox {
+    let dock_box = GtkBox::new(orientation, style.spacing);
+    dock_box.set_halign(gtk::Align::Center);
+    dock_box.set_margin(style.padding);
+
     // Apply CSS styling
     let provider = gtk::CssProvider::new();
     provider
@@ -179,6 +670,30 @@ pub fn create_dock(app: &Application, config: &DockConfig) {
                 color: white;
                 font-size: 9px;
             }
+            label.count {
+                font-weight: bold;
+            }
+            button.focused {
+                background-color: rgba(90, 90, 90, 0.9);
+                border-bottom: 2px solid rgba(120, 170, 240, 0.9);
+            }
+            button.other-workspace {
+                opacity: 0.6;
+            }
+            button.urgent {
+                animation: urgent-blink 1s ease-in-out infinite;
+            }
+            button.workspace {
+                padding: 3px 8px;
+            }
+            button.workspace.visible {
+                background-color: rgba(70, 70, 70, 0.9);
+            }
+            @keyframes urgent-blink {
+                50% {
+                    background-color: rgba(200, 60, 60, 0.9);
+                }
+            }
         ",
         )
         .unwrap();
@@ -188,128 +703,1709 @@ pub fn create_dock(app: &Application, config: &DockConfig) {
         gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
     );
 
-    dock_window.add(&dock_box);
-    detection_window.show_all();
+    dock_box
 }
 
-// Update our list of Sway windows
-fn update_window_list(connection: &mut Connection, windows: &Arc<Mutex<Vec<WindowInfo>>>) {
-    if let Ok(tree) = connection.get_tree() {
-        let mut window_list = Vec::new();
-        extract_windows(&tree, &mut window_list);
-
-        let mut windows_guard = windows.lock().unwrap();
-        *windows_guard = window_list;
+// Take a one-off snapshot of the Sway windows, empty if Sway is unreachable
+pub fn snapshot_windows() -> Vec<WindowInfo> {
+    let mut windows = Vec::new();
+    match sway::get_tree() {
+        Ok(tree) => extract_windows(&tree, &mut windows),
+        Err(e) => warn!("Failed to get Sway windows, dock will be empty: {}", e),
     }
+    windows
 }
 
-// Extract window information from Sway tree
-fn extract_windows(node: &Node, windows: &mut Vec<WindowInfo>) {
-    // Check if this node is an application window
-    if node.node_type == swayipc::NodeType::Con
-        && !node.name.is_none()
-        && (node.app_id.is_some() || node.window_properties.is_some())
-    {
-        // This is an app window
-        let title = node.name.clone().unwrap_or_default();
-        let app_id = node.app_id.clone().unwrap_or_else(|| {
-            node.window_properties
-                .as_ref()
-                .and_then(|props| props.class.clone())
-                .unwrap_or_default()
-        });
+// The Sway workspaces, empty if Sway is unreachable
+fn snapshot_workspaces() -> Vec<Workspace> {
+    sway::get_workspaces().unwrap_or_else(|e| {
+        warn!("Failed to get Sway workspaces: {}", e);
+        Vec::new()
+    })
+}
 
-        windows.push(WindowInfo {
-            id: node.id,
-            title,
-            app_id,
-            focused: node.focused,
-        });
+// The names of the workspaces shown on an output, empty if Sway is unreachable
+pub fn visible_workspaces() -> Vec<String> {
+    visible_names(&snapshot_workspaces())
+}
+
+fn visible_names(workspaces: &[Workspace]) -> Vec<String> {
+    workspaces
+        .iter()
+        .filter(|workspace| workspace.visible)
+        .map(|workspace| workspace.name.clone())
+        .collect()
+}
+
+// Where a node sits in the Sway tree
+#[derive(Clone, Copy, Default)]
+struct Placement<'a> {
+    output: Option<&'a str>,
+    workspace: Option<&'a str>,
+}
+
+fn extract_windows(tree: &Node, windows: &mut Vec<WindowInfo>) {
+    extract_windows_under(tree, Placement::default(), windows);
+}
+
+// Extract window information from Sway tree, noting the output and workspace
+// of each window on the way down
+fn extract_windows_under<'a>(
+    node: &'a Node,
+    mut placement: Placement<'a>,
+    windows: &mut Vec<WindowInfo>,
+) {
+    match node.node_type {
+        NodeType::Output => placement.output = node.name.as_deref(),
+        NodeType::Workspace => placement.workspace = node.name.as_deref(),
+        _ => windows.extend(WindowInfo::from_node(node, placement)),
     }
 
     // Recursively check children
     for child in &node.nodes {
-        extract_windows(child, windows);
+        extract_windows_under(child, placement, windows);
     }
     for child in &node.floating_nodes {
-        extract_windows(child, windows);
+        extract_windows_under(child, placement, windows);
     }
 }
 
-// Update the dock UI with current windows
-fn update_dock_ui(
-    dock_box: &GtkBox,
-    windows: &Arc<Mutex<Vec<WindowInfo>>>,
-    orientation: Orientation,
-) {
-    // Clear existing children
-    for child in dock_box.children() {
-        dock_box.remove(&child);
+/// Called with an app id to pin (true) or unpin (false) it
+pub type PinCallback = Rc<dyn Fn(&str, bool)>;
+
+// What a dock button stands for, used to match buttons across updates
+#[derive(Clone, Debug, PartialEq)]
+enum ItemKey {
+    // A pinned app, shown whether or not it is running
+    Pinned(String),
+    // The running windows of an app that is not pinned, in grouping mode
+    Group(String),
+    // A running window of an app that is not pinned
+    Window(i64),
+}
+
+/// One dock button: a pinned app, a group of windows or a single window
+#[derive(Clone, Debug, PartialEq)]
+pub struct DockItem {
+    key: ItemKey,
+    app_id: String,
+    title: String,
+    // Running windows as id and title, in tree order
+    windows: Vec<(i64, String)>,
+    // The focused window, if it is one of these
+    focused: Option<i64>,
+    // Whether a window wants attention
+    urgent: bool,
+    // Whether all windows are on workspaces not shown on any output
+    other_workspace: bool,
+    // Whether every window floats, and whether any is fullscreen
+    floating: bool,
+    fullscreen: bool,
+    // Where a lone window is, as "workspace on output"
+    location: Option<String>,
+}
+
+impl DockItem {
+    // Build an item for the windows of one app
+    fn for_app(
+        key: ItemKey,
+        app_id: &str,
+        windows: &[&WindowInfo],
+        visible_workspaces: &[String],
+    ) -> Self {
+        let focused = windows.iter().find(|window| window.focused);
+        // A lone window shows its title, several show the app name
+        let title = match windows {
+            [window] => window.title.clone(),
+            _ => desktop::app_info(app_id).name,
+        };
+        DockItem {
+            key,
+            app_id: app_id.to_string(),
+            title,
+            windows: windows
+                .iter()
+                .map(|window| (window.id, window.title.clone()))
+                .collect(),
+            focused: focused.map(|window| window.id),
+            urgent: windows.iter().any(|window| window.urgent),
+            other_workspace: !windows.is_empty()
+                && windows.iter().all(|window| {
+                    window
+                        .workspace
+                        .as_ref()
+                        .is_some_and(|workspace| !visible_workspaces.contains(workspace))
+                }),
+            floating: !windows.is_empty() && windows.iter().all(|window| window.floating),
+            fullscreen: windows.iter().any(|window| window.fullscreen),
+            location: match windows {
+                [window] => window.location(),
+                _ => None,
+            },
+        }
+    }
+
+    fn pinned(&self) -> bool {
+        matches!(self.key, ItemKey::Pinned(_))
+    }
+
+    // The window the menu and middle click act on: the focused one, or else
+    // the first
+    fn target_window(&self) -> Option<i64> {
+        self.focused
+            .or_else(|| self.windows.first().map(|(id, _)| *id))
+    }
+
+    // The window a click should focus: the one after the focused window, so
+    // repeated clicks cycle through the group, or else the first
+    fn next_window(&self) -> Option<i64> {
+        let ids: Vec<i64> = self.windows.iter().map(|(id, _)| *id).collect();
+        step_window(&ids, self.focused, true)
+    }
+}
+
+// The window before or after the focused one in `windows`, wrapping around.
+// Without a focused window, stepping forward starts at the first and
+// backward at the last.
+fn step_window(windows: &[i64], focused: Option<i64>, forward: bool) -> Option<i64> {
+    if windows.is_empty() {
+        return None;
+    }
+    let current = windows.iter().position(|id| Some(*id) == focused);
+    let index = match (current, forward) {
+        (Some(index), true) => (index + 1) % windows.len(),
+        (Some(index), false) => (index + windows.len() - 1) % windows.len(),
+        (None, true) => 0,
+        (None, false) => windows.len() - 1,
+    };
+    Some(windows[index])
+}
+
+// Whether a scroll event moves forward through windows, None for smooth
+// scrolling without a direction
+fn scroll_forward(event: &gdk::EventScroll) -> Option<bool> {
+    match event.direction() {
+        gdk::ScrollDirection::Down | gdk::ScrollDirection::Right => Some(true),
+        gdk::ScrollDirection::Up | gdk::ScrollDirection::Left => Some(false),
+        _ => None,
     }
+}
 
-    // Get window list
-    let windows_guard = match windows.lock() {
-        Ok(guard) => guard,
-        Err(_) => return,
+/// The dock buttons for the running windows and pinned apps. Pinned apps
+/// come first, in the order they were pinned, and take in their windows.
+/// With `group` set, the windows of other apps share one button per app.
+pub fn dock_items(
+    windows: &[WindowInfo],
+    pinned: &[String],
+    group: bool,
+    visible_workspaces: &[String],
+) -> Vec<DockItem> {
+    let is_pinned = |app_id: &str| pinned.iter().any(|app| app.eq_ignore_ascii_case(app_id));
+    let windows_of = |app_id: &str| -> Vec<&WindowInfo> {
+        windows
+            .iter()
+            .filter(|window| !window.title.is_empty() && window.app_id.eq_ignore_ascii_case(app_id))
+            .collect()
     };
 
-    // Add a button for each window
-    for window in windows_guard.iter() {
-        if window.title.is_empty() {
+    let mut items: Vec<DockItem> = pinned
+        .iter()
+        .map(|app| {
+            DockItem::for_app(
+                ItemKey::Pinned(app.clone()),
+                app,
+                &windows_of(app),
+                visible_workspaces,
+            )
+        })
+        .collect();
+
+    for window in windows {
+        if window.title.is_empty() || is_pinned(&window.app_id) {
+            continue;
+        }
+        if !group {
+            items.push(DockItem::for_app(
+                ItemKey::Window(window.id),
+                &window.app_id,
+                &[window],
+                visible_workspaces,
+            ));
             continue;
         }
 
-        // Create button for each window
-        let button = Button::new();
-        let container_box = match orientation {
-            Orientation::Horizontal => GtkBox::new(Orientation::Vertical, 2),
-            Orientation::Vertical => GtkBox::new(Orientation::Horizontal, 2),
-            _ => GtkBox::new(Orientation::Horizontal, 2),
+        // Groups sit where their first window would
+        let key = ItemKey::Group(window.app_id.to_lowercase());
+        if !items.iter().any(|item| item.key == key) {
+            items.push(DockItem::for_app(
+                key,
+                &window.app_id,
+                &windows_of(&window.app_id),
+                visible_workspaces,
+            ));
+        }
+    }
+    items
+}
+
+/// The buttons of one dock instance, kept in step with the dock items
+pub struct DockView {
+    dock_box: GtkBox,
+    orientation: Orientation,
+    style: DockStyle,
+    on_pin: PinCallback,
+    // The output this instance is on, if known
+    output: Option<String>,
+    workspaces: Option<WorkspaceStrip>,
+    previews: Option<Rc<Previews>>,
+    buttons: RefCell<Vec<DockButton>>,
+    // Pointer position along the dock while zooming, in window coordinates
+    pointer: Cell<Option<f64>>,
+    animating: Cell<bool>,
+}
+
+impl DockView {
+    pub fn new(
+        dock_box: &GtkBox,
+        orientation: Orientation,
+        style: DockStyle,
+        on_pin: PinCallback,
+    ) -> Self {
+        DockView {
+            dock_box: dock_box.clone(),
+            orientation,
+            style,
+            on_pin,
+            output: None,
+            workspaces: None,
+            previews: None,
+            buttons: RefCell::new(Vec::new()),
+            pointer: Cell::new(None),
+            animating: Cell::new(false),
+        }
+    }
+
+    fn on_output(self, output: Option<String>) -> Self {
+        DockView { output, ..self }
+    }
+
+    fn with_workspaces(self, strip: WorkspaceStrip) -> Self {
+        DockView {
+            workspaces: Some(strip),
+            ..self
+        }
+    }
+
+    fn with_previews(self, previews: Rc<Previews>) -> Self {
+        DockView {
+            previews: Some(previews),
+            ..self
+        }
+    }
+
+    // Show the workspaces on this instance's output, or all of them when the
+    // output is unknown
+    fn sync_workspaces(&self, workspaces: &[Workspace]) {
+        let Some(strip) = &self.workspaces else {
+            return;
         };
+        let shown: Vec<&Workspace> = workspaces
+            .iter()
+            .filter(|workspace| {
+                self.output
+                    .as_ref()
+                    .is_none_or(|output| *output == workspace.output)
+            })
+            .collect();
+        strip.sync(&shown);
+    }
 
-        // Add icon
-        let icon_name = get_icon_for_app(&window.app_id);
-        let icon = Image::from_icon_name(Some(&icon_name), gtk::IconSize::Dnd);
-        container_box.pack_start(&icon, true, true, 0);
+    /// Update the dock to show `items`, touching only the buttons of items
+    /// that were added, removed, moved or changed
+    pub fn sync(&self, items: &[DockItem]) {
+        let mut buttons = self.buttons.borrow_mut();
 
-        // Add label with orientation-aware positioning
-        let label = Label::new(Some(&window.title));
-        label.set_max_width_chars(10);
-        label.set_ellipsize(pango::EllipsizeMode::End);
-        container_box.pack_start(&label, false, false, 0);
+        // Drop the buttons of items that went away
+        buttons.retain(|button| {
+            let keep = items.iter().any(|item| item.key == button.key());
+            if !keep {
+                self.dock_box.remove(&button.button);
+            }
+            keep
+        });
 
-        button.add(&container_box);
+        for (index, item) in items.iter().enumerate() {
+            match buttons.iter().position(|button| button.key() == item.key) {
+                Some(current) => {
+                    buttons[current].update(item);
+                    if current != index {
+                        let button = buttons.remove(current);
+                        self.dock_box.reorder_child(&button.button, index as i32);
+                        buttons.insert(index, button);
+                    }
+                }
+                None => {
+                    let button = DockButton::new(
+                        item,
+                        self.orientation,
+                        self.style,
+                        self.on_pin.clone(),
+                        self.previews.clone(),
+                    );
+                    self.dock_box.pack_start(&button.button, false, false, 0);
+                    self.dock_box.reorder_child(&button.button, index as i32);
+                    button.button.show_all();
+                    buttons.insert(index, button);
+                }
+            }
+        }
+    }
 
-        // Connect click to focus window
-        let window_id = window.id;
-        button.connect_clicked(move |_| {
-            if let Ok(mut conn) = Connection::new() {
-                let _ = conn.run_command(format!("[con_id={}] focus", window_id));
+    // Zoom the icons around `pointer`, or shrink them back when None,
+    // easing towards the new sizes on every frame
+    fn magnify(self: &Rc<Self>, pointer: Option<f64>) {
+        self.pointer.set(pointer);
+        if self.animating.replace(true) {
+            return;
+        }
+        let view = Rc::downgrade(self);
+        self.dock_box.add_tick_callback(move |_, _| {
+            let Some(view) = view.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            if view.step_magnification() {
+                glib::ControlFlow::Continue
+            } else {
+                view.animating.set(false);
+                glib::ControlFlow::Break
             }
         });
+    }
+
+    // Move each icon a step towards its zoomed size, returning whether any
+    // is still on its way
+    fn step_magnification(&self) -> bool {
+        let mut moving = false;
+        for button in self.buttons.borrow().iter() {
+            // Windowless widgets are allocated relative to the dock window,
+            // as is the pointer position
+            let allocation = button.button.allocation();
+            let center = match self.orientation {
+                Orientation::Vertical => {
+                    f64::from(allocation.y()) + f64::from(allocation.height()) / 2.0
+                }
+                _ => f64::from(allocation.x()) + f64::from(allocation.width()) / 2.0,
+            };
+            let target = self
+                .pointer
+                .get()
+                .map_or(1.0, |pointer| self.style.scale_at((pointer - center).abs()));
+            let current = button.scale.get();
+            let next = if (target - current).abs() < 0.01 {
+                target
+            } else {
+                moving = true;
+                current + (target - current) * 0.35
+            };
+            if next != current {
+                button.scale.set(next);
+                button.icon.set_pixel_size(self.style.pixel_size(next));
+            }
+        }
+        moving
+    }
 
-        dock_box.pack_start(&button, false, false, 5);
+    /// Focus the window before or after the focused one, going through the
+    /// windows of every button in dock order
+    pub fn cycle(&self, forward: bool) {
+        let (windows, focused) = {
+            let buttons = self.buttons.borrow();
+            let items: Vec<_> = buttons.iter().map(|button| button.item.borrow()).collect();
+            let windows: Vec<i64> = items
+                .iter()
+                .flat_map(|item| item.windows.iter().map(|(id, _)| *id))
+                .collect();
+            (windows, items.iter().find_map(|item| item.focused))
+        };
+        if let Some(id) = step_window(&windows, focused, forward) {
+            window_command(id, "focus");
+        }
     }
+}
 
-    dock_box.show_all();
+// When the dock shows and hides around the pointer
+#[derive(Clone, Copy, Default)]
+struct Timing {
+    hide_delay: Duration,
+    reveal_delay: Duration,
+    // How far the pointer has to move along the detection strip, in
+    // pixels. The strip sits at the screen edge, so pushing against the
+    // edge moves nothing; this only tells a deliberate sweep along the
+    // edge from the pointer crossing it.
+    reveal_travel: f64,
 }
 
-// Try to find an appropriate icon for the app
-fn get_icon_for_app(app_id: &str) -> String {
-    match app_id.to_lowercase() {
-        id if id.contains("firefox") => "firefox",
-        id if id.contains("chrome") => "google-chrome",
-        id if id.contains("terminal") => "terminal",
-        id if id.contains("code") => "visual-studio-code",
-        _ => "application-x-executable",
-    }
-    .to_string()
+// Shows and hides one dock window following the autohide mode
+struct Autohide {
+    mode: AutohideConfig,
+    timing: Timing,
+    window: ApplicationWindow,
+    detector: ApplicationWindow,
+    content: GtkBox,
+    edge: EdgeConfig,
+    monitor: Option<gdk::Monitor>,
+    output: Option<String>,
+    // Whether the dock is shown by name, see `visibility`
+    enabled: Cell<bool>,
+    // Whether the pointer is on the dock or revealed it
+    hovered: Cell<bool>,
+    // Whether no window is in the way, for the modes that hide for windows
+    clear: Cell<bool>,
+    // Pending hide after the pointer left, and reveal after it reached the edge
+    hide_timer: RefCell<Option<glib::SourceId>>,
+    reveal_timer: RefCell<Option<glib::SourceId>>,
+    // Distance moved along the strip so far and where the pointer last
+    // was, None once far enough to reveal
+    travel: Cell<Option<(f64, (f64, f64))>>,
 }
 
-#[derive(Clone, Debug)]
-struct WindowInfo {
-    id: i64,
-    title: String,
-    app_id: String,
-    focused: bool,
+impl Autohide {
+    fn set_hovered(&self, hovered: bool) {
+        self.cancel_hide();
+        self.hovered.set(hovered);
+        self.update();
+    }
+
+    // Start measuring travel, revealing at once when none is needed
+    fn enter_strip(self: &Rc<Self>, position: (f64, f64)) {
+        self.cancel_hide();
+        if self.timing.reveal_travel <= 0.0 {
+            self.travel.set(None);
+            self.schedule_reveal();
+        } else {
+            self.travel.set(Some((0.0, position)));
+        }
+    }
+
+    fn move_on_strip(self: &Rc<Self>, position: (f64, f64)) {
+        let Some((travel, (x, y))) = self.travel.get() else {
+            return;
+        };
+        let travel = travel + (position.0 - x).abs() + (position.1 - y).abs();
+        if travel < self.timing.reveal_travel {
+            self.travel.set(Some((travel, position)));
+        } else {
+            self.travel.set(None);
+            self.schedule_reveal();
+        }
+    }
+
+    // Leaving the strip before the reveal delay is over cancels the reveal
+    fn leave_strip(&self) {
+        self.travel.set(None);
+        if let Some(source) = self.reveal_timer.take() {
+            source.remove();
+        }
+    }
+
+    fn schedule_reveal(self: &Rc<Self>) {
+        if self.timing.reveal_delay.is_zero() {
+            self.set_hovered(true);
+            return;
+        }
+        if self.reveal_timer.borrow().is_some() {
+            return;
+        }
+        let autohide = Rc::downgrade(self);
+        let source = glib::timeout_add_local_once(self.timing.reveal_delay, move || {
+            if let Some(autohide) = autohide.upgrade() {
+                autohide.reveal_timer.take();
+                autohide.set_hovered(true);
+            }
+        });
+        *self.reveal_timer.borrow_mut() = Some(source);
+    }
+
+    // Hide once the pointer has been away for the hide delay, restarting
+    // the wait if it was already running
+    fn schedule_hide(self: &Rc<Self>) {
+        self.cancel_hide();
+        let autohide = Rc::downgrade(self);
+        let source = glib::timeout_add_local_once(self.timing.hide_delay, move || {
+            if let Some(autohide) = autohide.upgrade() {
+                autohide.hide_timer.take();
+                autohide.set_hovered(false);
+            }
+        });
+        *self.hide_timer.borrow_mut() = Some(source);
+    }
+
+    fn cancel_hide(&self) {
+        if let Some(source) = self.hide_timer.take() {
+            source.remove();
+        }
+    }
+
+    // Look again at the windows on this dock's output
+    fn check_windows(&self, windows: &[WindowInfo], visible_workspaces: &[String]) {
+        let dock_rect = match self.mode {
+            AutohideConfig::Intelligent => self.dock_rect(),
+            _ => None,
+        };
+        self.clear.set(dock_clear(
+            self.mode,
+            windows,
+            visible_workspaces,
+            self.output.as_deref(),
+            dock_rect,
+        ));
+        self.update();
+    }
+
+    // A dock that never hides needs no strip to reveal it, and one hidden
+    // by name should not be revealed
+    fn show_detector(&self) {
+        if self.enabled.get() && self.mode != AutohideConfig::Never {
+            self.detector.show_all();
+        } else {
+            self.detector.hide();
+        }
+    }
+
+    fn update(&self) {
+        let shown = self.enabled.get()
+            && match self.mode {
+                AutohideConfig::Never => true,
+                AutohideConfig::Always => self.hovered.get(),
+                AutohideConfig::Intelligent | AutohideConfig::Fullscreen => {
+                    self.hovered.get() || self.clear.get()
+                }
+            };
+        if shown && !self.window.is_visible() {
+            self.window.show_all();
+        } else if !shown && self.window.is_visible() {
+            self.window.hide();
+        }
+    }
+
+    // Where the dock is in Sway's layout coordinates: its thickness along
+    // the edge, as long as its buttons, or the whole edge before they have
+    // been laid out
+    fn dock_rect(&self) -> Option<gdk::Rectangle> {
+        let screen = self.monitor.as_ref()?.geometry();
+        let (window_width, window_height) = self.window.size();
+        let horizontal = matches!(self.edge, EdgeConfig::Top | EdgeConfig::Bottom);
+        let (length, screen_length) = if horizontal {
+            (self.content.allocated_width(), screen.width())
+        } else {
+            (self.content.allocated_height(), screen.height())
+        };
+        let length = if length > 1 { length } else { screen_length };
+        let start = (screen_length - length) / 2;
+        Some(match self.edge {
+            EdgeConfig::Top => {
+                gdk::Rectangle::new(screen.x() + start, screen.y(), length, window_height)
+            }
+            EdgeConfig::Bottom => gdk::Rectangle::new(
+                screen.x() + start,
+                screen.y() + screen.height() - window_height,
+                length,
+                window_height,
+            ),
+            EdgeConfig::Left => {
+                gdk::Rectangle::new(screen.x(), screen.y() + start, window_width, length)
+            }
+            EdgeConfig::Right => gdk::Rectangle::new(
+                screen.x() + screen.width() - window_width,
+                screen.y() + start,
+                window_width,
+                length,
+            ),
+        })
+    }
+}
+
+impl Toggle for Autohide {
+    fn set_visible(&self, visible: bool) {
+        self.leave_strip();
+        self.cancel_hide();
+        self.hovered.set(false);
+        self.enabled.set(visible);
+        self.show_detector();
+        self.update();
+    }
+}
+
+// Whether no window is in the way of a dock in the given autohide mode.
+// Only windows on visible workspaces count, and only those on the dock's
+// output when it is known. Intellihide needs the dock's rect to compare
+// windows with, and keeps the dock up without one.
+fn dock_clear(
+    mode: AutohideConfig,
+    windows: &[WindowInfo],
+    visible_workspaces: &[String],
+    output: Option<&str>,
+    dock_rect: Option<gdk::Rectangle>,
+) -> bool {
+    let mut on_screen = windows.iter().filter(|window| {
+        output.is_none_or(|output| window.output.as_deref() == Some(output))
+            && window
+                .workspace
+                .as_ref()
+                .is_some_and(|workspace| visible_workspaces.contains(workspace))
+    });
+    match mode {
+        AutohideConfig::Never | AutohideConfig::Always => true,
+        AutohideConfig::Fullscreen => !on_screen.any(|window| window.fullscreen),
+        AutohideConfig::Intelligent => match dock_rect {
+            Some(dock) => !on_screen.any(|window| window.rect.intersect(&dock).is_some()),
+            None => true,
+        },
+    }
+}
+
+// Workspace buttons that switch to their workspace on click and take window
+// buttons dropped on them
+struct WorkspaceStrip {
+    strip_box: GtkBox,
+    buttons: RefCell<Vec<(String, Button)>>,
+}
+
+impl WorkspaceStrip {
+    fn new(orientation: Orientation) -> Self {
+        let strip_box = GtkBox::new(orientation, 2);
+        strip_box.style_context().add_class("workspaces");
+        WorkspaceStrip {
+            strip_box,
+            buttons: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Rebuild the buttons when the workspaces change, otherwise only
+    // restyle them
+    fn sync(&self, workspaces: &[&Workspace]) {
+        let mut buttons = self.buttons.borrow_mut();
+        let same = buttons.len() == workspaces.len()
+            && buttons
+                .iter()
+                .zip(workspaces)
+                .all(|((name, _), workspace)| *name == workspace.name);
+        if !same {
+            for (_, button) in buttons.drain(..) {
+                self.strip_box.remove(&button);
+            }
+            for workspace in workspaces {
+                let button = workspace_button(&workspace.name);
+                self.strip_box.pack_start(&button, false, false, 0);
+                button.show_all();
+                buttons.push((workspace.name.clone(), button));
+            }
+        }
+
+        for ((_, button), workspace) in buttons.iter().zip(workspaces) {
+            let style = button.style_context();
+            for (class, on) in [
+                ("focused", workspace.focused),
+                ("visible", workspace.visible),
+                ("urgent", workspace.urgent),
+            ] {
+                if on {
+                    style.add_class(class);
+                } else {
+                    style.remove_class(class);
+                }
+            }
+        }
+    }
+}
+
+// A button switching to a workspace, moving windows dropped on it there
+fn workspace_button(name: &str) -> Button {
+    let button = Button::with_label(name);
+    button.style_context().add_class("workspace");
+    {
+        let name = name.to_string();
+        button.connect_clicked(move |_| {
+            if let Err(e) = sway::run_command(&format!("workspace {}", quote(&name))) {
+                error!("Failed to switch to workspace {}: {}", name, e);
+            }
+        });
+    }
+
+    button.drag_dest_set(
+        gtk::DestDefaults::ALL,
+        &[window_target()],
+        gdk::DragAction::MOVE,
+    );
+    let name = name.to_string();
+    button.connect_drag_data_received(move |_, _, _, _, data, _, _| {
+        let id = String::from_utf8(data.data())
+            .ok()
+            .and_then(|id| id.parse::<i64>().ok());
+        if let Some(id) = id {
+            window_command(id, &format!("move container to workspace {}", quote(&name)));
+        }
+    });
+    button
+}
+
+fn window_target() -> gtk::TargetEntry {
+    gtk::TargetEntry::new(WINDOW_TARGET, gtk::TargetFlags::SAME_APP, 0)
+}
+
+// A dock button focusing a window, or launching a pinned app that is not
+// running. Long-pressing a button with several windows lists them, as does
+// its right-click menu.
+struct DockButton {
+    button: Button,
+    icon: Image,
+    label: Label,
+    count: Label,
+    style: DockStyle,
+    // Current zoom of the icon
+    scale: Cell<f64>,
+    item: Rc<RefCell<DockItem>>,
+    // Kept alive while the button exists
+    _menu: gtk::Menu,
+    _long_press: gtk::GestureLongPress,
+}
+
+impl DockButton {
+    fn new(
+        item: &DockItem,
+        orientation: Orientation,
+        style: DockStyle,
+        on_pin: PinCallback,
+        previews: Option<Rc<Previews>>,
+    ) -> Self {
+        let button = Button::new();
+        let container_box = match orientation {
+            Orientation::Horizontal => GtkBox::new(Orientation::Vertical, 2),
+            Orientation::Vertical => GtkBox::new(Orientation::Horizontal, 2),
+            _ => GtkBox::new(Orientation::Horizontal, 2),
+        };
+
+        // Add icon
+        let icon = Image::new();
+        set_app_icon(&icon, &item.app_id, style.icon_size);
+        container_box.pack_start(&icon, true, true, 0);
+
+        // Add label with orientation-aware positioning
+        let label = Label::new(Some(&item.title));
+        label.set_max_width_chars(style.label_width);
+        label.set_ellipsize(pango::EllipsizeMode::End);
+        label.set_no_show_all(style.labels != LabelConfig::Below);
+        label.set_visible(style.labels == LabelConfig::Below);
+        container_box.pack_start(&label, false, false, 0);
+
+        // Window count, shown for groups
+        let count = Label::new(None);
+        count.style_context().add_class("count");
+        count.set_no_show_all(true);
+        container_box.pack_start(&count, false, false, 0);
+        show_count(&count, item.windows.len());
+
+        button.add(&container_box);
+        show_state(&button, item, style.labels);
+        let item = Rc::new(RefCell::new(item.clone()));
+
+        // List the windows to pick from on a long press, which then should
+        // not count as a click
+        let long_pressed = Rc::new(Cell::new(false));
+        let long_press = gtk::GestureLongPress::new(&button);
+        {
+            let button = button.clone();
+            let item = item.clone();
+            let long_pressed = long_pressed.clone();
+            long_press.connect_pressed(move |_, _, _| {
+                if item.borrow().windows.len() > 1 {
+                    long_pressed.set(true);
+                    show_window_list(&button, &item.borrow());
+                }
+            });
+        }
+
+        // Focus the window, or start the app
+        {
+            let item = item.clone();
+            button.connect_clicked(move |_| {
+                if long_pressed.replace(false) {
+                    return;
+                }
+                let item = item.borrow();
+                let command = match item.next_window() {
+                    Some(id) => format!("[con_id={}] focus", id),
+                    None => {
+                        let exec = desktop::app_info(&item.app_id).exec;
+                        format!("exec {}", exec.as_deref().unwrap_or(&item.app_id))
+                    }
+                };
+                if let Err(e) = sway::run_command(&command) {
+                    error!("Failed to activate {}: {}", item.app_id, e);
+                }
+            });
+        }
+
+        if let Some(previews) = previews {
+            connect_preview(&button, &item, previews);
+        }
+
+        // Scrolling over a group cycles through its windows; other buttons
+        // leave the scroll to the dock
+        button.add_events(gdk::EventMask::SCROLL_MASK);
+        {
+            let item = item.clone();
+            button.connect_scroll_event(move |_, event| {
+                let item = item.borrow();
+                if item.windows.len() < 2 {
+                    return glib::Propagation::Proceed;
+                }
+                let ids: Vec<i64> = item.windows.iter().map(|(id, _)| *id).collect();
+                if let Some(id) = scroll_forward(event)
+                    .and_then(|forward| step_window(&ids, item.focused, forward))
+                {
+                    window_command(id, "focus");
+                }
+                glib::Propagation::Stop
+            });
+        }
+
+        // Window buttons can be dropped on a workspace to move the window
+        button.drag_source_set(
+            gdk::ModifierType::BUTTON1_MASK,
+            &[window_target()],
+            gdk::DragAction::MOVE,
+        );
+        {
+            let item = item.clone();
+            button.connect_drag_data_get(move |_, _, data, _, _| {
+                if let Some(id) = item.borrow().target_window() {
+                    data.set(&data.target(), 8, id.to_string().as_bytes());
+                }
+            });
+        }
+
+        // Right click opens the window menu, middle click closes the window
+        let menu = gtk::Menu::new();
+        menu.set_attach_widget(Some(&button));
+        {
+            let item = item.clone();
+            let menu = menu.clone();
+            button.connect_button_press_event(move |button, event| {
+                let item = item.borrow();
+                match event.button() {
+                    2 => {
+                        if let Some(id) = item.target_window() {
+                            window_command(id, "kill");
+                        }
+                        true.into()
+                    }
+                    3 if !item.app_id.is_empty() => {
+                        fill_window_menu(&menu, button, &item, &on_pin);
+                        menu.show_all();
+                        menu.popup_at_pointer(Some(event));
+                        true.into()
+                    }
+                    _ => false.into(),
+                }
+            });
+        }
+
+        DockButton {
+            button,
+            icon,
+            label,
+            count,
+            style,
+            scale: Cell::new(1.0),
+            item,
+            _menu: menu,
+            _long_press: long_press,
+        }
+    }
+
+    fn key(&self) -> ItemKey {
+        self.item.borrow().key.clone()
+    }
+
+    // Refresh the parts of the button that show changed details
+    fn update(&self, item: &DockItem) {
+        let mut current = self.item.borrow_mut();
+        if item.title != current.title {
+            self.label.set_text(&item.title);
+        }
+        if item.app_id != current.app_id {
+            let size = self.style.pixel_size(self.scale.get());
+            set_app_icon(&self.icon, &item.app_id, size);
+        }
+        if item.windows.len() != current.windows.len() {
+            show_count(&self.count, item.windows.len());
+        }
+        show_state(&self.button, item, self.style.labels);
+        *current = item.clone();
+    }
+}
+
+// Mark a button focused, urgent or on a hidden workspace for the stylesheet,
+// and say where its window is in the tooltip
+fn show_state(button: &Button, item: &DockItem, labels: LabelConfig) {
+    let tooltip = match (&item.location, labels) {
+        (_, LabelConfig::None) => None,
+        (Some(location), _) => Some(format!("{}\n{}", item.title, location)),
+        (None, _) => Some(item.title.clone()),
+    };
+    button.set_tooltip_text(tooltip.as_deref());
+
+    let style = button.style_context();
+    for (class, on) in [
+        ("focused", item.focused.is_some()),
+        ("urgent", item.urgent),
+        ("other-workspace", item.other_workspace),
+        ("floating", item.floating),
+        ("fullscreen", item.fullscreen),
+    ] {
+        if on {
+            style.add_class(class);
+        } else {
+            style.remove_class(class);
+        }
+    }
+}
+
+// Rebuild the right-click menu for an item. Window entries act on the
+// item's target window; the workspace and output lists are read when the
+// menu opens.
+fn fill_window_menu(menu: &gtk::Menu, button: &Button, item: &DockItem, on_pin: &PinCallback) {
+    for child in menu.children() {
+        menu.remove(&child);
+    }
+
+    // The same list a long press shows, for those who never hold a button
+    if item.windows.len() > 1 {
+        let windows = gtk::MenuItem::with_label("Choose window");
+        let button = button.downgrade();
+        let item = item.clone();
+        windows.connect_activate(move |_| {
+            if let Some(button) = button.upgrade() {
+                show_window_list(&button, &item);
+            }
+        });
+        menu.append(&windows);
+        menu.append(&gtk::SeparatorMenuItem::new());
+    }
+
+    if let Some(id) = item.target_window() {
+        let close = gtk::MenuItem::with_label("Close");
+        close.connect_activate(move |_| window_command(id, "kill"));
+        menu.append(&close);
+
+        match sway::get_workspaces() {
+            Ok(workspaces) => {
+                let names = workspaces.into_iter().map(|workspace| workspace.name);
+                menu.append(&move_menu(id, "Move to workspace", "workspace", names));
+            }
+            Err(e) => warn!("Failed to list Sway workspaces: {}", e),
+        }
+        match sway::get_outputs() {
+            Ok(outputs) => {
+                let names = outputs
+                    .into_iter()
+                    .filter(|output| output.active)
+                    .map(|output| output.name);
+                menu.append(&move_menu(id, "Move to output", "output", names));
+            }
+            Err(e) => warn!("Failed to list Sway outputs: {}", e),
+        }
+
+        for (label, command) in [
+            ("Toggle floating", "floating toggle"),
+            ("Toggle fullscreen", "fullscreen toggle"),
+            ("Send to scratchpad", "move scratchpad"),
+        ] {
+            let entry = gtk::MenuItem::with_label(label);
+            entry.connect_activate(move |_| window_command(id, command));
+            menu.append(&entry);
+        }
+        menu.append(&gtk::SeparatorMenuItem::new());
+    }
+
+    let pin = gtk::MenuItem::with_label(if item.pinned() {
+        "Unpin from dock"
+    } else {
+        "Pin to dock"
+    });
+    let app_id = item.app_id.clone();
+    let pinned = item.pinned();
+    let on_pin = on_pin.clone();
+    pin.connect_activate(move |_| on_pin(&app_id, !pinned));
+    menu.append(&pin);
+}
+
+// A submenu moving a window to one of `targets`, a list of workspace or
+// output names
+fn move_menu(
+    id: i64,
+    label: &str,
+    kind: &'static str,
+    targets: impl Iterator<Item = String>,
+) -> gtk::MenuItem {
+    let entry = gtk::MenuItem::with_label(label);
+    let submenu = gtk::Menu::new();
+    for target in targets {
+        let target_entry = gtk::MenuItem::with_label(&target);
+        target_entry.connect_activate(move |_| {
+            window_command(
+                id,
+                &format!("move container to {} {}", kind, quote(&target)),
+            )
+        });
+        submenu.append(&target_entry);
+    }
+    entry.set_submenu(Some(&submenu));
+    entry
+}
+
+// Run a Sway command on one window, logging failures
+fn window_command(id: i64, command: &str) {
+    if let Err(e) = sway::run_command(&format!("[con_id={}] {}", id, command)) {
+        error!("Failed to run {} on window {}: {}", command, id, e);
+    }
+}
+
+// Quote a workspace or output name for a Sway command
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Pop up a picture of the button's window once the pointer rests on it,
+// taking a new one while it shows
+fn connect_preview(button: &Button, item: &Rc<RefCell<DockItem>>, previews: Rc<Previews>) {
+    let popover = gtk::Popover::new(Some(button));
+    popover.set_modal(false);
+    popover.set_constrain_to(gtk::PopoverConstraint::None);
+    popover.style_context().add_class("preview");
+    let timer: Rc<RefCell<Option<glib::SourceId>>> = Rc::default();
+    let cancel = {
+        let timer = timer.clone();
+        let popover = popover.clone();
+        move || {
+            if let Some(source) = timer.take() {
+                source.remove();
+            }
+            popover.popdown();
+        }
+    };
+
+    {
+        let item = item.clone();
+        let popover = popover.clone();
+        let cancel = cancel.clone();
+        button.connect_enter_notify_event(move |_, _| {
+            cancel();
+            let item = item.clone();
+            let popover = popover.clone();
+            let previews = previews.clone();
+            let source = {
+                let timer = timer.clone();
+                glib::timeout_add_local_once(PREVIEW_DELAY, move || {
+                    timer.take();
+                    let (id, app_id, title) = {
+                        let item = item.borrow();
+                        let Some(id) = item.target_window() else {
+                            return;
+                        };
+                        let title = item
+                            .windows
+                            .iter()
+                            .find(|(other, _)| *other == id)
+                            .map_or_else(|| item.title.clone(), |(_, title)| title.clone());
+                        (id, item.app_id.clone(), title)
+                    };
+                    set_preview(&popover, &app_id, &title, previews.cached(id).as_ref());
+                    popover.popup();
+
+                    let popover = popover.clone();
+                    previews.refresh(id, move |pixbuf| {
+                        if let Some(pixbuf) = pixbuf.filter(|_| popover.is_visible()) {
+                            set_preview(&popover, &app_id, &title, Some(&pixbuf));
+                        }
+                    });
+                })
+            };
+            *timer.borrow_mut() = Some(source);
+            glib::Propagation::Proceed
+        });
+    }
+    {
+        let cancel = cancel.clone();
+        button.connect_leave_notify_event(move |_, _| {
+            cancel();
+            glib::Propagation::Proceed
+        });
+    }
+    button.connect_clicked(move |_| cancel());
+}
+
+fn set_preview(popover: &gtk::Popover, app_id: &str, title: &str, pixbuf: Option<&Pixbuf>) {
+    if let Some(child) = popover.child() {
+        popover.remove(&child);
+    }
+    let preview = preview_widget(app_id, title, pixbuf);
+    popover.add(&preview);
+    preview.show_all();
+}
+
+/// A window's picture over its title, or its app icon enlarged when there
+/// is no picture
+pub fn preview_widget(app_id: &str, title: &str, pixbuf: Option<&Pixbuf>) -> GtkBox {
+    let preview = GtkBox::new(Orientation::Vertical, 4);
+    preview.set_margin(6);
+    let image = match pixbuf {
+        Some(pixbuf) => Image::from_pixbuf(Some(pixbuf)),
+        None => {
+            let image = Image::new();
+            set_app_icon(&image, app_id, PREVIEW_ICON_SIZE);
+            image
+        }
+    };
+    preview.pack_start(&image, false, false, 0);
+
+    let label = Label::new(Some(title));
+    label.set_max_width_chars(PREVIEW_WIDTH / 8);
+    label.set_ellipsize(pango::EllipsizeMode::End);
+    preview.pack_start(&label, false, false, 0);
+    preview
+}
+
+/// Capture the focused window and build its preview, waiting up to
+/// `timeout` for the capture. Falls back to the app icon like the dock.
+pub fn focused_window_preview(timeout: Duration) -> Result<GtkBox, String> {
+    let windows = snapshot_windows();
+    let window = windows
+        .iter()
+        .find(|window| window.focused)
+        .ok_or("No focused window to preview")?;
+    let previews = Previews::with_default_sources();
+    previews.set_targets(preview_targets(&windows, &visible_workspaces()));
+
+    let captured: Rc<RefCell<Option<Pixbuf>>> = Rc::default();
+    let done = Rc::new(Cell::new(false));
+    {
+        let captured = captured.clone();
+        let done = done.clone();
+        previews.refresh(window.id, move |pixbuf| {
+            *captured.borrow_mut() = pixbuf;
+            done.set(true);
+        });
+    }
+    let deadline = glib::monotonic_time() + timeout.as_micros() as i64;
+    while !done.get() && glib::monotonic_time() < deadline {
+        // Captures run in another process, so wait for them without spinning
+        glib::MainContext::default().iteration(false);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let pixbuf = captured.borrow().clone();
+    Ok(preview_widget(
+        &window.app_id,
+        &window.title,
+        pixbuf.as_ref(),
+    ))
+}
+
+// What the preview sources need to know of each window
+fn preview_targets(windows: &[WindowInfo], visible_workspaces: &[String]) -> Vec<PreviewTarget> {
+    windows
+        .iter()
+        .map(|window| PreviewTarget {
+            id: window.id,
+            rect: window.rect,
+            on_screen: window
+                .workspace
+                .as_ref()
+                .is_some_and(|workspace| visible_workspaces.contains(workspace)),
+        })
+        .collect()
+}
+
+// Show how many windows a button stands for, when there is more than one
+fn show_count(count: &Label, windows: usize) {
+    count.set_text(&windows.to_string());
+    count.set_visible(windows > 1);
+}
+
+// Pop up the titles of an item's windows under its button, focusing the one picked
+fn show_window_list(button: &Button, item: &DockItem) {
+    let popover = gtk::Popover::new(Some(button));
+    let list = GtkBox::new(Orientation::Vertical, 2);
+    list.set_margin(4);
+    for (id, title) in &item.windows {
+        let entry = Button::with_label(title);
+        entry.set_relief(gtk::ReliefStyle::None);
+        if let Some(label) = entry
+            .child()
+            .and_then(|child| child.downcast::<Label>().ok())
+        {
+            label.set_max_width_chars(40);
+            label.set_ellipsize(pango::EllipsizeMode::End);
+            label.set_xalign(0.0);
+        }
+        if Some(*id) == item.focused {
+            entry.style_context().add_class("focused");
+        }
+
+        let id = *id;
+        let popover_ref = popover.downgrade();
+        entry.connect_clicked(move |_| {
+            if let Err(e) = sway::run_command(&format!("[con_id={}] focus", id)) {
+                error!("Failed to focus window: {}", e);
+            }
+            if let Some(popover) = popover_ref.upgrade() {
+                popover.popdown();
+            }
+        });
+        list.pack_start(&entry, false, false, 0);
+    }
+    popover.add(&list);
+    list.show_all();
+    popover.popup();
+}
+
+// Show the app's icon from its desktop entry, which may name a theme icon
+// or point at an image file. Both follow the image's pixel size, so icons
+// can be zoomed.
+fn set_app_icon(image: &Image, app_id: &str, size: i32) {
+    let icon = desktop::app_info(app_id).icon;
+    let path = Path::new(&icon);
+    if path.is_absolute() && path.is_file() {
+        let file = gio::FileIcon::new(&gio::File::for_path(path));
+        image.set_from_gicon(&file, gtk::IconSize::Dnd);
+    } else {
+        if path.is_absolute() {
+            warn!("Failed to load icon {}: no such file", icon);
+        }
+        image.set_from_icon_name(Some(&icon), gtk::IconSize::Dnd);
+    }
+    image.set_pixel_size(size);
+}
+
+#[derive(Clone, Debug)]
+pub struct WindowInfo {
+    id: i64,
+    title: String,
+    app_id: String,
+    focused: bool,
+    urgent: bool,
+    // Where the window is, None for windows that are not on a workspace yet
+    workspace: Option<String>,
+    output: Option<String>,
+    floating: bool,
+    fullscreen: bool,
+    // Geometry in layout coordinates
+    rect: gdk::Rectangle,
+}
+
+impl WindowInfo {
+    // Whether the dock on `output` shows this window. Docks whose output is
+    // unknown show every window.
+    fn shown_on(
+        &self,
+        filter: DockFilter,
+        output: Option<&str>,
+        visible_workspaces: &[String],
+    ) -> bool {
+        let Some(output) = output else {
+            return true;
+        };
+        let on_output = self.output.as_deref() == Some(output);
+        match filter {
+            DockFilter::All => true,
+            DockFilter::Output => on_output,
+            DockFilter::Workspace => {
+                on_output
+                    && self
+                        .workspace
+                        .as_ref()
+                        .is_some_and(|workspace| visible_workspaces.contains(workspace))
+            }
+        }
+    }
+
+    fn location(&self) -> Option<String> {
+        match (&self.workspace, &self.output) {
+            (Some(workspace), Some(output)) => Some(format!("{} on {}", workspace, output)),
+            (Some(workspace), None) => Some(workspace.clone()),
+            _ => None,
+        }
+    }
+
+    // Window details of an application window node, None for other nodes
+    fn from_node(node: &Node, placement: Placement) -> Option<WindowInfo> {
+        if !matches!(node.node_type, NodeType::Con | NodeType::FloatingCon)
+            || (node.app_id.is_none() && node.window_properties.is_none())
+        {
+            return None;
+        }
+
+        let app_id = node.app_id.clone().unwrap_or_else(|| {
+            node.window_properties
+                .as_ref()
+                .and_then(|props| props.class.clone())
+                .unwrap_or_default()
+        });
+        Some(WindowInfo {
+            id: node.id,
+            title: node.name.clone().unwrap_or_default(),
+            app_id,
+            focused: node.focused,
+            urgent: node.urgent,
+            workspace: placement.workspace.map(str::to_string),
+            output: placement.output.map(str::to_string),
+            floating: node.node_type == NodeType::FloatingCon,
+            fullscreen: node.fullscreen_mode.is_some_and(|mode| mode > 0),
+            rect: gdk::Rectangle::new(node.rect.x, node.rect.y, node.rect.width, node.rect.height),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: i64, app_id: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            id,
+            title: title.to_string(),
+            app_id: app_id.to_string(),
+            focused: false,
+            urgent: false,
+            workspace: Some("1".to_string()),
+            output: Some("DP-1".to_string()),
+            floating: false,
+            fullscreen: false,
+            rect: gdk::Rectangle::new(0, 0, 100, 100),
+        }
+    }
+
+    fn keys(items: &[DockItem]) -> Vec<ItemKey> {
+        items.iter().map(|item| item.key.clone()).collect()
+    }
+
+    #[test]
+    fn pinned_apps_come_first_and_take_their_windows() {
+        let windows = [
+            window(1, "foot", "shell"),
+            window(2, "firefox", "news"),
+            window(3, "Firefox", "mail"),
+        ];
+        let items = dock_items(&windows, &["firefox".to_string()], false, &[]);
+        assert_eq!(
+            keys(&items),
+            [ItemKey::Pinned("firefox".to_string()), ItemKey::Window(1)]
+        );
+        assert_eq!(
+            items[0].windows,
+            [(2, "news".to_string()), (3, "mail".to_string())]
+        );
+    }
+
+    #[test]
+    fn pinned_apps_show_without_windows() {
+        let items = dock_items(&[], &["foot".to_string()], true, &[]);
+        assert_eq!(keys(&items), [ItemKey::Pinned("foot".to_string())]);
+        assert!(items[0].windows.is_empty());
+        assert_eq!(items[0].target_window(), None);
+        assert!(!items[0].other_workspace);
+    }
+
+    #[test]
+    fn grouping_shares_a_button_where_the_first_window_is() {
+        let windows = [
+            window(1, "foot", "one"),
+            window(2, "firefox", "news"),
+            window(3, "Foot", "two"),
+        ];
+        let items = dock_items(&windows, &[], true, &[]);
+        assert_eq!(
+            keys(&items),
+            [
+                ItemKey::Group("foot".to_string()),
+                ItemKey::Group("firefox".to_string())
+            ]
+        );
+        assert_eq!(
+            items[0].windows,
+            [(1, "one".to_string()), (3, "two".to_string())]
+        );
+        // A lone window still shows its own title
+        assert_eq!(items[1].title, "news");
+    }
+
+    #[test]
+    fn untitled_windows_are_left_out() {
+        let windows = [window(1, "foot", ""), window(2, "foot", "shell")];
+        let items = dock_items(&windows, &[], false, &[]);
+        assert_eq!(keys(&items), [ItemKey::Window(2)]);
+    }
+
+    #[test]
+    fn items_show_window_state() {
+        let mut focused = window(1, "foot", "one");
+        focused.focused = true;
+        let mut urgent = window(2, "foot", "two");
+        urgent.urgent = true;
+        urgent.workspace = Some("2".to_string());
+        let items = dock_items(&[focused, urgent], &[], true, &["1".to_string()]);
+        assert_eq!(items[0].focused, Some(1));
+        assert!(items[0].urgent);
+        // One of the windows is on a visible workspace
+        assert!(!items[0].other_workspace);
+        assert_eq!(items[0].target_window(), Some(1));
+        assert_eq!(items[0].next_window(), Some(2));
+
+        let hidden = window(3, "foot", "three");
+        let items = dock_items(&[hidden], &[], false, &["2".to_string()]);
+        assert!(items[0].other_workspace);
+        assert_eq!(items[0].location.as_deref(), Some("1 on DP-1"));
+    }
+
+    #[test]
+    fn docks_show_windows_by_filter() {
+        let visible = ["1".to_string()];
+        let here = window(1, "foot", "here");
+        let mut elsewhere = window(2, "foot", "elsewhere");
+        elsewhere.output = Some("HDMI-A-1".to_string());
+        let mut hidden = window(3, "foot", "hidden");
+        hidden.workspace = Some("2".to_string());
+        let mut unplaced = window(4, "foot", "unplaced");
+        unplaced.workspace = None;
+
+        let shown = |filter| {
+            [&here, &elsewhere, &hidden, &unplaced]
+                .iter()
+                .filter(|window| window.shown_on(filter, Some("DP-1"), &visible))
+                .map(|window| window.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(shown(DockFilter::All), [1, 2, 3, 4]);
+        assert_eq!(shown(DockFilter::Output), [1, 3, 4]);
+        assert_eq!(shown(DockFilter::Workspace), [1]);
+    }
+
+    #[test]
+    fn docks_on_unknown_outputs_show_every_window() {
+        let mut elsewhere = window(1, "foot", "elsewhere");
+        elsewhere.output = Some("HDMI-A-1".to_string());
+        elsewhere.workspace = Some("2".to_string());
+        for filter in [DockFilter::All, DockFilter::Output, DockFilter::Workspace] {
+            assert!(elsewhere.shown_on(filter, None, &["1".to_string()]));
+        }
+    }
+
+    #[test]
+    fn fullscreen_windows_on_the_dock_output_hide_it() {
+        let visible = ["1".to_string()];
+        let mut fullscreen = window(1, "mpv", "video");
+        fullscreen.fullscreen = true;
+        let clear = |windows: &[WindowInfo], output| {
+            dock_clear(AutohideConfig::Fullscreen, windows, &visible, output, None)
+        };
+        assert!(clear(&[window(2, "foot", "shell")], Some("DP-1")));
+        assert!(!clear(&[fullscreen.clone()], Some("DP-1")));
+        assert!(clear(&[fullscreen.clone()], Some("HDMI-A-1")));
+        // Without a known output every visible window counts
+        assert!(!clear(&[fullscreen.clone()], None));
+
+        fullscreen.workspace = Some("2".to_string());
+        assert!(clear(&[fullscreen], Some("DP-1")));
+    }
+
+    #[test]
+    fn overlapping_windows_hide_an_intelligent_dock() {
+        let visible = ["1".to_string()];
+        let dock = Some(gdk::Rectangle::new(0, 1040, 1920, 40));
+        let mut overlapping = window(1, "foot", "tall");
+        overlapping.rect = gdk::Rectangle::new(0, 0, 960, 1080);
+        let mut above = window(2, "foot", "short");
+        above.rect = gdk::Rectangle::new(960, 0, 960, 1000);
+        let clear = |windows: &[WindowInfo], output, dock| {
+            dock_clear(AutohideConfig::Intelligent, windows, &visible, output, dock)
+        };
+        assert!(clear(&[above.clone()], Some("DP-1"), dock));
+        assert!(!clear(
+            &[above.clone(), overlapping.clone()],
+            Some("DP-1"),
+            dock
+        ));
+        assert!(clear(&[overlapping.clone()], Some("HDMI-A-1"), dock));
+        assert!(!clear(&[overlapping.clone()], None, dock));
+        // Without the dock's rect there is nothing to compare with
+        assert!(clear(&[overlapping.clone()], Some("DP-1"), None));
+
+        overlapping.workspace = Some("2".to_string());
+        assert!(clear(&[overlapping], Some("DP-1"), dock));
+    }
+
+    #[test]
+    fn other_modes_ignore_windows() {
+        let mut fullscreen = window(1, "mpv", "video");
+        fullscreen.fullscreen = true;
+        let dock = Some(gdk::Rectangle::new(0, 0, 100, 100));
+        for mode in [AutohideConfig::Never, AutohideConfig::Always] {
+            assert!(dock_clear(
+                mode,
+                std::slice::from_ref(&fullscreen),
+                &["1".to_string()],
+                Some("DP-1"),
+                dock
+            ));
+        }
+    }
+
+    #[test]
+    fn step_window_wraps_around() {
+        let windows = [1, 2, 3];
+        assert_eq!(step_window(&windows, Some(1), true), Some(2));
+        assert_eq!(step_window(&windows, Some(3), true), Some(1));
+        assert_eq!(step_window(&windows, Some(1), false), Some(3));
+        assert_eq!(step_window(&windows, Some(2), false), Some(1));
+    }
+
+    #[test]
+    fn step_window_starts_at_an_end_without_focus() {
+        let windows = [1, 2, 3];
+        assert_eq!(step_window(&windows, None, true), Some(1));
+        assert_eq!(step_window(&windows, None, false), Some(3));
+        assert_eq!(step_window(&windows, Some(9), true), Some(1));
+        assert_eq!(step_window(&[], Some(1), true), None);
+    }
+
+    #[test]
+    fn events_update_the_window_list() {
+        let mut windows = vec![window(1, "foot", "one"), window(2, "foot", "two")];
+
+        DockEvent::Focus(2).apply(&mut windows);
+        assert!(!windows[0].focused && windows[1].focused);
+
+        DockEvent::Title {
+            id: 1,
+            title: "renamed".to_string(),
+        }
+        .apply(&mut windows);
+        assert_eq!(windows[0].title, "renamed");
+
+        DockEvent::Urgent {
+            id: 1,
+            urgent: true,
+        }
+        .apply(&mut windows);
+        DockEvent::Floating {
+            id: 2,
+            floating: true,
+        }
+        .apply(&mut windows);
+        DockEvent::Fullscreen {
+            id: 1,
+            fullscreen: true,
+        }
+        .apply(&mut windows);
+        assert!(windows[0].urgent && windows[0].fullscreen && !windows[0].floating);
+        assert!(windows[1].floating && !windows[1].urgent);
+
+        // Events for windows the dock does not know are ignored
+        DockEvent::Title {
+            id: 9,
+            title: "nobody".to_string(),
+        }
+        .apply(&mut windows);
+        DockEvent::Close(1).apply(&mut windows);
+        assert_eq!(
+            windows.iter().map(|window| window.id).collect::<Vec<_>>(),
+            [2]
+        );
+
+        DockEvent::Snapshot(vec![window(5, "firefox", "news")]).apply(&mut windows);
+        assert_eq!(
+            windows.iter().map(|window| window.id).collect::<Vec<_>>(),
+            [5]
+        );
+    }
+
+    fn style(config: DockConfig) -> DockStyle {
+        DockStyle::new(&config)
+    }
+
+    #[test]
+    fn style_keeps_sizes_in_range() {
+        let style = style(DockConfig {
+            icon_size: 0,
+            spacing: -4,
+            padding: -1,
+            label_width: 0,
+            magnification: 0.5,
+            magnification_range: -10,
+            ..DockConfig::default()
+        });
+        assert_eq!(style.icon_size, 1);
+        assert_eq!(style.spacing, 0);
+        assert_eq!(style.padding, 0);
+        assert_eq!(style.label_width, 1);
+        assert_eq!(style.magnification, 1.0);
+        assert_eq!(style.magnification_range, 1.0);
+    }
+
+    #[test]
+    fn dock_size_fits_icons_and_labels() {
+        let labelled = DockStyle::default();
+        assert_eq!(dock_size(Orientation::Horizontal, &labelled), (800, 70));
+        // Vertical docks put labels beside the icon
+        assert_eq!(dock_size(Orientation::Vertical, &labelled), (54, 800));
+
+        let icons_only = style(DockConfig {
+            labels: LabelConfig::Tooltip,
+            icon_size: 48,
+            padding: 0,
+            ..DockConfig::default()
+        });
+        assert_eq!(dock_size(Orientation::Horizontal, &icons_only), (800, 60));
+    }
+
+    #[test]
+    fn magnification_falls_off_with_distance() {
+        let zoomed = style(DockConfig {
+            magnification: 2.0,
+            magnification_range: 120,
+            ..DockConfig::default()
+        });
+        assert_eq!(zoomed.scale_at(0.0), 2.0);
+        assert_eq!(zoomed.scale_at(60.0), 1.75);
+        assert_eq!(zoomed.scale_at(120.0), 1.0);
+        assert_eq!(zoomed.scale_at(500.0), 1.0);
+        assert_eq!(zoomed.pixel_size(1.5), 48);
+    }
+
+    #[test]
+    fn magnification_is_optional() {
+        assert!(!DockStyle::default().magnifies(Orientation::Horizontal));
+        assert_eq!(DockStyle::default().scale_at(0.0), 1.0);
+
+        let zoomed = style(DockConfig {
+            magnification: 2.0,
+            ..DockConfig::default()
+        });
+        let horizontal_only = style(DockConfig {
+            magnification: 2.0,
+            magnify_vertical: false,
+            ..DockConfig::default()
+        });
+        assert!(horizontal_only.magnifies(Orientation::Horizontal));
+        assert!(!horizontal_only.magnifies(Orientation::Vertical));
+
+        // Docks leave room for the largest icon
+        assert_eq!(dock_size(Orientation::Horizontal, &zoomed), (800, 102));
+        assert_eq!(
+            dock_size(Orientation::Vertical, &horizontal_only),
+            (54, 800)
+        );
+    }
 }
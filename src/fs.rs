@@ -0,0 +1,240 @@
+use mlua::{Function, Lua, Table};
+use std::cell::RefCell;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use crate::script::ScriptContext;
+
+/// Paths scripts are allowed to touch through the fs API
+pub struct FsPermissions {
+    roots: Vec<PathBuf>,
+}
+
+impl FsPermissions {
+    /// Build the allow list from config entries, expanding a leading `~`
+    pub fn new(allow: &[String]) -> Self {
+        let roots = allow
+            .iter()
+            .filter_map(|entry| match entry.strip_prefix('~') {
+                Some(rest) => dirs::home_dir().map(|home| home.join(rest.trim_start_matches('/'))),
+                None => Some(PathBuf::from(entry)),
+            })
+            .map(|root| normalize(&root))
+            .collect();
+        FsPermissions { roots }
+    }
+
+    /// Also allow access to `path` and everything below it
+    pub fn allow(&mut self, path: &Path) {
+        self.roots.push(normalize(path));
+    }
+
+    /// Resolve a path requested by a script, failing if it is not allowed
+    pub fn check(&self, path: &str) -> Result<PathBuf, mlua::Error> {
+        let resolved = normalize(Path::new(path));
+        if self.roots.iter().any(|root| resolved.starts_with(root)) {
+            Ok(resolved)
+        } else {
+            Err(mlua::Error::RuntimeError(format!(
+                "Permission denied: {} is outside the allowed paths (scripts.fs_allow)",
+                path
+            )))
+        }
+    }
+}
+
+// Make a path absolute and resolve symlinks and `..` where it exists, so
+// scripts cannot escape an allowed directory
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+
+    // The file may not exist yet, but its directory might be a symlink
+    match (normalized.parent(), normalized.file_name()) {
+        (Some(parent), Some(name)) => match parent.canonicalize() {
+            Ok(parent) => parent.join(name),
+            Err(_) => normalized,
+        },
+        _ => normalized,
+    }
+}
+
+/// Register the `fs` table in a script environment.
+///
+/// `fs.read(path)` and `fs.list(dir)` read synchronously, `fs.watch(path, fn)`
+/// calls `fn(event, path)` on changes and `fs.tail(path, fn)` calls `fn(line)`
+/// for every line appended to a file. Watches end when the script is unloaded.
+pub fn register_fs_api(lua: &Lua, env: &Table, ctx: &ScriptContext) -> Result<(), mlua::Error> {
+    let fs = lua.create_table()?;
+
+    // fs.read(path) returns the contents of a file
+    {
+        let ctx = ctx.clone();
+        let read = lua.create_function(move |_, path: String| {
+            let path = ctx.permissions().check(&path)?;
+            std::fs::read_to_string(&path).map_err(|e| {
+                mlua::Error::RuntimeError(format!("Failed to read {}: {}", path.display(), e))
+            })
+        })?;
+        fs.set("read", read)?;
+    }
+
+    // fs.list(dir) returns the sorted names of the entries in a directory
+    {
+        let ctx = ctx.clone();
+        let list = lua.create_function(move |_, path: String| {
+            let path = ctx.permissions().check(&path)?;
+            let list_error = |e: std::io::Error| {
+                mlua::Error::RuntimeError(format!("Failed to list {}: {}", path.display(), e))
+            };
+            let mut names = Vec::new();
+            for entry in std::fs::read_dir(&path).map_err(list_error)? {
+                let entry = entry.map_err(list_error)?;
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+            names.sort();
+            Ok(names)
+        })?;
+        fs.set("list", list)?;
+    }
+
+    // fs.watch(path, fn) calls fn(event, path) when the file or directory changes
+    {
+        let ctx = ctx.clone();
+        let watch = lua.create_function(move |lua, (path, func): (String, Function)| {
+            let path = ctx.permissions().check(&path)?;
+            let key = lua.create_registry_value(func)?;
+            let callback_ctx = ctx.clone();
+            let watch = ctx
+                .backend()
+                .watch(
+                    &path,
+                    Box::new(move |event, changed| {
+                        let Some(lua) = callback_ctx.lua() else {
+                            return;
+                        };
+                        let result = lua.registry_value::<Function>(&key).and_then(|func| {
+                            func.call::<_, ()>((event, changed.to_string_lossy().into_owned()))
+                        });
+                        if let Err(e) = result {
+                            callback_ctx.backend().script_error(e.to_string());
+                        }
+                    }),
+                )
+                .map_err(mlua::Error::RuntimeError)?;
+            ctx.keep_watch(watch);
+            Ok(())
+        })?;
+        fs.set("watch", watch)?;
+    }
+
+    // fs.tail(path, fn) calls fn(line) for each line appended to a file
+    {
+        let ctx = ctx.clone();
+        let tail = lua.create_function(move |lua, (path, func): (String, Function)| {
+            let path = ctx.permissions().check(&path)?;
+            let key = lua.create_registry_value(func)?;
+            let state = RefCell::new(TailState {
+                offset: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                partial: Vec::new(),
+            });
+            let callback_ctx = ctx.clone();
+            let tailed = path.clone();
+            let watch = ctx
+                .backend()
+                .watch(
+                    &path,
+                    Box::new(move |event, _| {
+                        if event == "deleted" {
+                            return;
+                        }
+                        let lines = match state.borrow_mut().read_lines(&tailed) {
+                            Ok(lines) => lines,
+                            Err(e) => {
+                                callback_ctx.backend().script_error(format!(
+                                    "Failed to tail {}: {}",
+                                    tailed.display(),
+                                    e
+                                ));
+                                return;
+                            }
+                        };
+                        let Some(lua) = callback_ctx.lua() else {
+                            return;
+                        };
+                        for line in lines {
+                            let result = lua
+                                .registry_value::<Function>(&key)
+                                .and_then(|func| func.call::<_, ()>(line));
+                            if let Err(e) = result {
+                                callback_ctx.backend().script_error(e.to_string());
+                                return;
+                            }
+                        }
+                    }),
+                )
+                .map_err(mlua::Error::RuntimeError)?;
+            ctx.keep_watch(watch);
+            Ok(())
+        })?;
+        fs.set("tail", tail)?;
+    }
+
+    env.set("fs", fs)?;
+
+    Ok(())
+}
+
+// Read position of a tailed file
+struct TailState {
+    offset: u64,
+    // Bytes after the last newline, which may end inside a UTF-8 character
+    partial: Vec<u8>,
+}
+
+impl TailState {
+    // Read the complete lines appended since the last call
+    fn read_lines(&mut self, path: &Path) -> std::io::Result<Vec<String>> {
+        let mut file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+
+        // Start over if the file was truncated or replaced
+        if len < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        self.offset += appended.len() as u64;
+        self.partial.extend_from_slice(&appended);
+
+        // Only decode complete lines, a newline never splits a character
+        let Some(end) = self.partial.iter().rposition(|&byte| byte == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+        Ok(String::from_utf8_lossy(&complete[..end])
+            .split('\n')
+            .map(str::to_string)
+            .collect())
+    }
+}
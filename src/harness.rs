@@ -0,0 +1,787 @@
+use gtk::Orientation;
+use gtk::glib;
+use gtk_layer_shell::{Edge, Layer};
+use mlua::{Table, Value};
+use serde_json::{Map, Value as JsonValue, json};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::backend::{
+    Backend, BoxHandle, ExecOutput, HttpResponse, LabelHandle, MoveCallback, Watch, WatchCallback,
+    WindowHandle,
+};
+use crate::config::ScriptsConfig;
+use crate::fs::FsPermissions;
+use crate::layout::PositionStore;
+use crate::script::ScriptManager;
+use crate::visibility::Transition;
+
+/// Run every test file headlessly, printing a report. Returns true if all passed.
+pub fn run(paths: &[PathBuf]) -> bool {
+    let mut failed = 0;
+
+    for path in paths {
+        match run_test(path) {
+            Ok(()) => println!("ok      {}", path.display()),
+            Err(errors) => {
+                failed += 1;
+                println!("FAILED  {}", path.display());
+                for error in errors {
+                    println!("    {}", error.replace('\n', "\n    "));
+                }
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", paths.len() - failed, failed);
+    failed == 0
+}
+
+// Run a single test file against a fresh mock backend
+fn run_test(path: &Path) -> Result<(), Vec<String>> {
+    let backend = Rc::new(MockBackend::default());
+    let base_dir = path
+        .parent()
+        .and_then(|dir| std::path::absolute(dir).ok())
+        .unwrap_or_default();
+
+    // Tests may use the default allow list and their own directory
+    let mut permissions = FsPermissions::new(&ScriptsConfig::default().fs_allow);
+    permissions.allow(&base_dir);
+    let manager = Rc::new(ScriptManager::with_backend(
+        backend.clone(),
+        permissions,
+        PositionStore::new(HashMap::new(), false),
+    ));
+
+    let result = register_test_api(&manager, backend.clone(), base_dir)
+        .and_then(|()| manager.load_script(path));
+
+    let mut errors = backend.errors.take();
+    if let Err(e) = result {
+        errors.insert(0, e.to_string());
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Expose the `test` table used by test files to drive the mock backend
+fn register_test_api(
+    manager: &Rc<ScriptManager>,
+    backend: Rc<MockBackend>,
+    base_dir: PathBuf,
+) -> Result<(), mlua::Error> {
+    let lua = manager.lua();
+    let test = lua.create_table()?;
+
+    // test.load(path) runs a widget script, relative to the test file
+    {
+        let manager = Rc::downgrade(manager);
+        let base_dir = base_dir.clone();
+        let load = lua.create_function(move |_, path: String| {
+            let manager = manager
+                .upgrade()
+                .ok_or_else(|| mlua::Error::RuntimeError("Script manager is gone".into()))?;
+            manager.load_script(&base_dir.join(path))
+        })?;
+        test.set("load", load)?;
+    }
+
+    // test.unload(path) unloads a widget script, cancelling its callbacks
+    {
+        let manager = Rc::downgrade(manager);
+        let base_dir = base_dir.clone();
+        let unload = lua.create_function(move |_, path: String| {
+            let manager = manager
+                .upgrade()
+                .ok_or_else(|| mlua::Error::RuntimeError("Script manager is gone".into()))?;
+            Ok(manager.unload_script(&base_dir.join(path)))
+        })?;
+        test.set("unload", unload)?;
+    }
+
+    // test.http(url, body, status) answers requests for url with body (tables
+    // become JSON) and status, 200 by default
+    {
+        let backend = backend.clone();
+        let http = lua.create_function(
+            move |_, (url, body, status): (String, Value, Option<u16>)| {
+                let body = match body {
+                    Value::String(s) => s.to_str()?.to_string(),
+                    other => lua_to_json(other)?.to_string(),
+                };
+                let response = HttpResponse {
+                    status: status.unwrap_or(200),
+                    body,
+                };
+                backend.http.borrow_mut().insert(url, response);
+                Ok(())
+            },
+        )?;
+        test.set("http", http)?;
+    }
+
+    // test.exec(command, result) answers command with a string stdout or a
+    // { status, stdout, stderr } table
+    {
+        let backend = backend.clone();
+        let exec = lua.create_function(move |_, (command, result): (String, Value)| {
+            let output = match result {
+                Value::String(s) => ExecOutput {
+                    status: 0,
+                    stdout: s.to_str()?.to_string(),
+                    stderr: String::new(),
+                },
+                Value::Table(t) => ExecOutput {
+                    status: t.get::<_, Option<i32>>("status")?.unwrap_or(0),
+                    stdout: t.get::<_, Option<String>>("stdout")?.unwrap_or_default(),
+                    stderr: t.get::<_, Option<String>>("stderr")?.unwrap_or_default(),
+                },
+                _ => {
+                    return Err(mlua::Error::RuntimeError(
+                        "exec fixture must be a string or table".to_string(),
+                    ));
+                }
+            };
+            backend.exec.borrow_mut().insert(command, output);
+            Ok(())
+        })?;
+        test.set("exec", exec)?;
+    }
+
+    // test.advance(ms) moves the virtual clock forward, firing due timers and
+    // delivering background results; test.advance(0) only delivers results
+    {
+        let backend = backend.clone();
+        let advance = lua.create_function(move |_, ms: u64| {
+            backend.advance(ms);
+            Ok(())
+        })?;
+        test.set("advance", advance)?;
+    }
+
+    // test.drag(index, dx, dy) drags the index-th window (as in test.tree())
+    // by an offset, as the user would in edit-layout mode
+    {
+        let backend = backend.clone();
+        let drag = lua.create_function(move |_, (index, dx, dy): (usize, i32, i32)| {
+            let window = index
+                .checked_sub(1)
+                .and_then(|index| backend.windows.borrow().get(index).cloned())
+                .ok_or_else(|| mlua::Error::RuntimeError(format!("No window {}", index)))?;
+            window.drag(dx, dy).map_err(mlua::Error::RuntimeError)
+        })?;
+        test.set("drag", drag)?;
+    }
+
+    // test.outputs(names) replaces the connected outputs, as if monitors had
+    // been plugged in or out
+    {
+        let backend = backend.clone();
+        let outputs = lua.create_function(move |_, names: Vec<String>| {
+            backend.set_outputs(names);
+            Ok(())
+        })?;
+        test.set("outputs", outputs)?;
+    }
+
+    // test.dispatch(ms) runs the GLib main loop for ms milliseconds of real
+    // time, delivering events from outside the mock such as D-Bus signals
+    {
+        let dispatch = lua.create_function(|_, ms: u64| {
+            let context = glib::MainContext::default();
+            let deadline = Instant::now() + Duration::from_millis(ms);
+            while Instant::now() < deadline {
+                if !context.iteration(false) {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+            }
+            Ok(())
+        })?;
+        test.set("dispatch", dispatch)?;
+    }
+
+    // test.fs_event(path, event) notifies watches on path or its directory,
+    // as if the file had been changed on disk
+    {
+        let backend = backend.clone();
+        let base_dir = base_dir.clone();
+        let fs_event = lua.create_function(move |_, (path, event): (String, Option<String>)| {
+            let path = base_dir.join(path);
+            backend.fs_event(&path, event.as_deref().unwrap_or("changed"));
+            Ok(())
+        })?;
+        test.set("fs_event", fs_event)?;
+    }
+
+    // test.now() returns the virtual clock in milliseconds
+    {
+        let backend = backend.clone();
+        let now = lua.create_function(move |_, ()| Ok(backend.now.get()))?;
+        test.set("now", now)?;
+    }
+
+    // test.tree() returns every window as a nested table
+    {
+        let backend = backend.clone();
+        let tree = lua.create_function(move |lua, ()| {
+            let windows = backend
+                .windows
+                .borrow()
+                .iter()
+                .map(|window| window.to_json())
+                .collect();
+            Ok(ScriptManager::serde_json_to_lua_value(
+                lua,
+                JsonValue::Array(windows),
+            ))
+        })?;
+        test.set("tree", tree)?;
+    }
+
+    // test.labels() returns the text of every label, in creation order
+    {
+        let backend = backend.clone();
+        let labels = lua.create_function(move |_, ()| Ok(backend.labels()))?;
+        test.set("labels", labels)?;
+    }
+
+    // test.find_label(pattern) returns the first label text matching a Lua pattern
+    {
+        let backend = backend.clone();
+        let find_label = lua.create_function(move |lua, pattern: String| {
+            let find: mlua::Function = lua.globals().get::<_, Table>("string")?.get("find")?;
+            for text in backend.labels() {
+                if find
+                    .call::<_, Option<i64>>((text.clone(), pattern.clone()))?
+                    .is_some()
+                {
+                    return Ok(Some(text));
+                }
+            }
+            Ok(None)
+        })?;
+        test.set("find_label", find_label)?;
+    }
+
+    // test.assert_eq(actual, expected, message) compares values structurally
+    {
+        let assert_eq = lua.create_function(
+            |_, (actual, expected, message): (Value, Value, Option<String>)| {
+                let actual = lua_to_json(actual)?;
+                let expected = lua_to_json(expected)?;
+                if actual != expected {
+                    return Err(mlua::Error::RuntimeError(format!(
+                        "{}: expected {}, got {}",
+                        message.as_deref().unwrap_or("assertion failed"),
+                        expected,
+                        actual
+                    )));
+                }
+                Ok(())
+            },
+        )?;
+        test.set("assert_eq", assert_eq)?;
+    }
+
+    // test.dir is the absolute directory of the test file
+    test.set("dir", base_dir.to_string_lossy().into_owned())?;
+
+    lua.globals().set("test", test)?;
+    Ok(())
+}
+
+// Convert a Lua value into JSON for fixtures and comparisons
+fn lua_to_json(value: Value) -> Result<JsonValue, mlua::Error> {
+    Ok(match value {
+        Value::Nil => JsonValue::Null,
+        Value::Boolean(b) => JsonValue::Bool(b),
+        Value::Integer(i) => json!(i),
+        Value::Number(n) => json!(n),
+        Value::String(s) => JsonValue::String(s.to_str()?.to_string()),
+        Value::Table(table) => {
+            if table.raw_len() > 0 {
+                let mut items = Vec::new();
+                for value in table.sequence_values::<Value>() {
+                    items.push(lua_to_json(value?)?);
+                }
+                JsonValue::Array(items)
+            } else {
+                let mut map = Map::new();
+                for pair in table.pairs::<String, Value>() {
+                    let (key, value) = pair?;
+                    map.insert(key, lua_to_json(value)?);
+                }
+                JsonValue::Object(map)
+            }
+        }
+        other => JsonValue::String(format!("<{}>", other.type_name())),
+    })
+}
+
+struct MockTimer {
+    due: u64,
+    seq: u64,
+    interval: u64,
+    callback: Box<dyn FnMut() -> bool>,
+}
+
+/// Backend without a display: widgets are recorded in a tree, timers run on
+/// a virtual clock and HTTP/exec requests are answered from fixtures.
+#[derive(Default)]
+pub struct MockBackend {
+    windows: RefCell<Vec<Rc<MockNode>>>,
+    now: Cell<u64>,
+    next_seq: Cell<u64>,
+    timers: RefCell<Vec<MockTimer>>,
+    http: RefCell<HashMap<String, HttpResponse>>,
+    exec: RefCell<HashMap<String, ExecOutput>>,
+    watches: RefCell<Vec<Weak<MockWatch>>>,
+    outputs: RefCell<Vec<String>>,
+    output_watches: RefCell<Vec<Weak<MockOutputsWatch>>>,
+    errors: RefCell<Vec<String>>,
+}
+
+impl MockBackend {
+    // Advance the virtual clock, running timers in due order
+    fn advance(&self, ms: u64) {
+        let target = self.now.get() + ms;
+        loop {
+            let next = self
+                .timers
+                .borrow()
+                .iter()
+                .enumerate()
+                .filter(|(_, timer)| timer.due <= target)
+                .min_by_key(|(_, timer)| (timer.due, timer.seq))
+                .map(|(index, _)| index);
+            let Some(index) = next else {
+                break;
+            };
+
+            // Run the callback without holding the borrow, it may add timers
+            let mut timer = self.timers.borrow_mut().remove(index);
+            self.now.set(timer.due);
+            if (timer.callback)() {
+                timer.due += timer.interval.max(1);
+                timer.seq = self.bump_seq();
+                self.timers.borrow_mut().push(timer);
+            }
+        }
+        self.now.set(target);
+    }
+
+    // Deliver a background result on the next clock tick, like the main loop would
+    fn defer<T: 'static>(&self, result: T, done: Box<dyn FnOnce(T)>) {
+        let mut pending = Some((result, done));
+        self.add_timeout(
+            Duration::ZERO,
+            Box::new(move || {
+                if let Some((result, done)) = pending.take() {
+                    done(result);
+                }
+                false
+            }),
+        );
+    }
+
+    // Replace the connected outputs and notify output watches
+    fn set_outputs(&self, outputs: Vec<String>) {
+        *self.outputs.borrow_mut() = outputs;
+        let watches: Vec<Rc<MockOutputsWatch>> = self
+            .output_watches
+            .borrow()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for watch in watches {
+            (watch.callback)();
+        }
+    }
+
+    // Call the live watches on path or on its parent directory
+    fn fs_event(&self, path: &Path, event: &str) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let watches: Vec<Rc<MockWatch>> = self
+            .watches
+            .borrow()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|watch| watch.path == path || Some(watch.path.as_path()) == path.parent())
+            .collect();
+        for watch in watches {
+            (watch.callback)(event, &path);
+        }
+    }
+
+    fn http_response(&self, url: &str) -> Result<HttpResponse, String> {
+        self.http
+            .borrow()
+            .get(url)
+            .cloned()
+            .ok_or_else(|| format!("No HTTP fixture for {}", url))
+    }
+
+    fn bump_seq(&self) -> u64 {
+        let seq = self.next_seq.get();
+        self.next_seq.set(seq + 1);
+        seq
+    }
+
+    fn labels(&self) -> Vec<String> {
+        let mut labels = Vec::new();
+        for window in self.windows.borrow().iter() {
+            window.collect_labels(&mut labels);
+        }
+        labels
+    }
+}
+
+impl Backend for MockBackend {
+    fn create_window(
+        &self,
+        title: &str,
+        width: i32,
+        height: i32,
+        output: Option<&str>,
+    ) -> Rc<dyn WindowHandle> {
+        let window = MockNode::new(
+            "window",
+            json!({
+                "title": title,
+                "output": output,
+                "width": width,
+                "height": height,
+                "visible": false,
+                "layer": "background",
+                "anchor_top": true,
+                "anchor_bottom": false,
+                "anchor_left": true,
+                "anchor_right": false,
+                "exclusive_zone": 0,
+                "namespace": "swaydgets",
+                "click_through": false,
+                "transition": "none",
+                "transition_duration": 0,
+            }),
+        );
+        self.windows.borrow_mut().push(window.clone());
+        window
+    }
+
+    fn outputs(&self) -> Vec<String> {
+        self.outputs.borrow().clone()
+    }
+
+    fn watch_outputs(&self, callback: Box<dyn Fn()>) -> Result<Box<dyn Watch>, String> {
+        let watch = Rc::new(MockOutputsWatch { callback });
+        self.output_watches.borrow_mut().push(Rc::downgrade(&watch));
+        Ok(Box::new(MockWatchGuard { _watch: watch }))
+    }
+
+    fn add_timeout(&self, interval: Duration, callback: Box<dyn FnMut() -> bool>) {
+        let interval = interval.as_millis() as u64;
+        let timer = MockTimer {
+            due: self.now.get() + interval,
+            seq: self.bump_seq(),
+            interval,
+            callback,
+        };
+        self.timers.borrow_mut().push(timer);
+    }
+
+    fn http_get(&self, url: &str) -> Result<String, String> {
+        let response = self.http_response(url)?;
+        if !(200..300).contains(&response.status) {
+            return Err(format!("HTTP error: {}", response.status));
+        }
+        Ok(response.body)
+    }
+
+    fn http_get_async(&self, url: &str, done: Box<dyn FnOnce(Result<HttpResponse, String>)>) {
+        self.defer(self.http_response(url), done);
+    }
+
+    fn exec(&self, command: &str) -> Result<ExecOutput, String> {
+        self.exec
+            .borrow()
+            .get(command)
+            .cloned()
+            .ok_or_else(|| format!("No exec fixture for {}", command))
+    }
+
+    fn exec_async(&self, command: &str, done: Box<dyn FnOnce(Result<ExecOutput, String>)>) {
+        self.defer(self.exec(command), done);
+    }
+
+    fn watch(&self, path: &Path, callback: WatchCallback) -> Result<Box<dyn Watch>, String> {
+        let watch = Rc::new(MockWatch {
+            path: path.to_path_buf(),
+            callback,
+        });
+        self.watches.borrow_mut().push(Rc::downgrade(&watch));
+        Ok(Box::new(MockWatchGuard { _watch: watch }))
+    }
+
+    fn script_error(&self, message: String) {
+        self.errors.borrow_mut().push(message);
+    }
+}
+
+/// A watch registered on the mock backend, triggered by `test.fs_event`
+struct MockWatch {
+    path: PathBuf,
+    callback: WatchCallback,
+}
+
+// Keeps a mock watch alive for as long as the script holds it
+struct MockWatchGuard<T> {
+    _watch: Rc<T>,
+}
+
+impl<T> Watch for MockWatchGuard<T> {}
+
+/// An output watch registered on the mock backend, triggered by `test.outputs`
+struct MockOutputsWatch {
+    callback: Box<dyn Fn()>,
+}
+
+/// A recorded widget: its kind, its properties and its children
+struct MockNode {
+    kind: &'static str,
+    props: RefCell<Map<String, JsonValue>>,
+    children: RefCell<Vec<Rc<MockNode>>>,
+    on_moved: RefCell<Option<MoveCallback>>,
+}
+
+impl MockNode {
+    fn new(kind: &'static str, props: JsonValue) -> Rc<Self> {
+        let JsonValue::Object(props) = props else {
+            unreachable!("mock props are always objects");
+        };
+        Rc::new(MockNode {
+            kind,
+            props: RefCell::new(props),
+            children: RefCell::new(Vec::new()),
+            on_moved: RefCell::new(None),
+        })
+    }
+
+    fn int(&self, key: &str) -> i32 {
+        self.props
+            .borrow()
+            .get(key)
+            .and_then(JsonValue::as_i64)
+            .unwrap_or(0) as i32
+    }
+
+    // The edges whose margins move a window horizontally and vertically, as
+    // in layout::drag_edges
+    fn drag_edges(&self) -> [Option<(&'static str, i32)>; 2] {
+        let anchored = |edge: &str| {
+            self.props
+                .borrow()
+                .get(&format!("anchor_{}", edge))
+                .and_then(JsonValue::as_bool)
+                .unwrap_or(false)
+        };
+        let along = |start: &'static str, end: &'static str| {
+            if anchored(start) {
+                Some((start, 1))
+            } else if anchored(end) {
+                Some((end, -1))
+            } else {
+                None
+            }
+        };
+        [along("left", "right"), along("top", "bottom")]
+    }
+
+    // Move a draggable window as a drag in edit-layout mode would
+    fn drag(&self, dx: i32, dy: i32) -> Result<(), String> {
+        let on_moved = self.on_moved.borrow();
+        let Some(on_moved) = on_moved.as_ref() else {
+            return Err("window is not draggable".to_string());
+        };
+
+        let mut position = [0, 0];
+        for ((edge, delta), coordinate) in self
+            .drag_edges()
+            .into_iter()
+            .zip([dx, dy])
+            .zip(&mut position)
+        {
+            if let Some((edge, sign)) = edge {
+                let key = format!("margin_{}", edge);
+                let margin = (self.int(&key) + sign * delta).max(0);
+                self.set(&key, margin);
+                *coordinate = margin;
+            }
+        }
+        on_moved(position[0], position[1]);
+        Ok(())
+    }
+
+    fn set(&self, key: &str, value: impl Into<JsonValue>) {
+        self.props
+            .borrow_mut()
+            .insert(key.to_string(), value.into());
+    }
+
+    fn push_css(&self, css: &str) {
+        let mut props = self.props.borrow_mut();
+        let entry = props
+            .entry("css")
+            .or_insert_with(|| JsonValue::Array(Vec::new()));
+        if let JsonValue::Array(styles) = entry {
+            styles.push(css.into());
+        }
+    }
+
+    fn add_child(&self, child: Rc<MockNode>) -> Rc<MockNode> {
+        self.children.borrow_mut().push(child.clone());
+        child
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let mut node = self.props.borrow().clone();
+        node.insert("type".to_string(), self.kind.into());
+        node.insert(
+            "children".to_string(),
+            self.children
+                .borrow()
+                .iter()
+                .map(|child| child.to_json())
+                .collect(),
+        );
+        JsonValue::Object(node)
+    }
+
+    fn collect_labels(&self, labels: &mut Vec<String>) {
+        if self.kind == "label"
+            && let Some(JsonValue::String(text)) = self.props.borrow().get("text")
+        {
+            labels.push(text.clone());
+        }
+        for child in self.children.borrow().iter() {
+            child.collect_labels(labels);
+        }
+    }
+}
+
+impl WindowHandle for MockNode {
+    fn set_margin(&self, edge: Edge, margin: i32) {
+        self.set(&format!("margin_{}", edge_name(edge)), margin);
+    }
+
+    fn set_layer(&self, layer: Layer) {
+        let layer = match layer {
+            Layer::Background => "background",
+            Layer::Bottom => "bottom",
+            Layer::Top => "top",
+            _ => "overlay",
+        };
+        self.set("layer", layer);
+    }
+
+    fn set_anchor(&self, edge: Edge, anchored: bool) {
+        self.set(&format!("anchor_{}", edge_name(edge)), anchored);
+    }
+
+    fn set_exclusive_zone(&self, zone: i32) {
+        self.set("exclusive_zone", zone);
+    }
+
+    fn set_namespace(&self, namespace: &str) {
+        self.set("namespace", namespace);
+    }
+
+    fn set_click_through(&self, enabled: bool) {
+        self.set("click_through", enabled);
+    }
+
+    fn make_draggable(&self, position: Option<(i32, i32)>, on_moved: MoveCallback) {
+        if let Some((x, y)) = position {
+            for (edge, margin) in self.drag_edges().into_iter().zip([x, y]) {
+                if let Some((edge, _)) = edge {
+                    self.set(&format!("margin_{}", edge), margin);
+                }
+            }
+        }
+        self.set("draggable", true);
+        *self.on_moved.borrow_mut() = Some(on_moved);
+    }
+
+    fn set_transition(&self, transition: Transition) {
+        self.set("transition", transition.name());
+        self.set("transition_duration", transition.duration());
+    }
+
+    fn show(&self) {
+        self.set("visible", true);
+    }
+
+    fn hide(&self) {
+        self.set("visible", false);
+    }
+
+    fn close(&self) {
+        self.set("visible", false);
+        self.set("closed", true);
+    }
+
+    fn add_box(&self, orientation: Orientation, spacing: i32) -> Rc<dyn BoxHandle> {
+        let orientation = match orientation {
+            Orientation::Vertical => "vertical",
+            _ => "horizontal",
+        };
+        self.add_child(MockNode::new(
+            "box",
+            json!({ "orientation": orientation, "spacing": spacing, "interactive": false }),
+        ))
+    }
+}
+
+impl BoxHandle for MockNode {
+    fn add_label(&self, text: &str, font_size: i32) -> Rc<dyn LabelHandle> {
+        self.add_child(MockNode::new(
+            "label",
+            json!({ "text": text, "font_size": font_size }),
+        ))
+    }
+
+    fn set_css(&self, css: &str) {
+        self.push_css(css);
+    }
+
+    fn set_interactive(&self, interactive: bool) {
+        self.set("interactive", interactive);
+    }
+}
+
+impl LabelHandle for MockNode {
+    fn set_text(&self, text: &str) {
+        self.set("text", text);
+    }
+
+    fn set_css(&self, css: &str) {
+        self.push_css(css);
+    }
+}
+
+// Name of an edge in window props
+fn edge_name(edge: Edge) -> &'static str {
+    match edge {
+        Edge::Top => "top",
+        Edge::Bottom => "bottom",
+        Edge::Left => "left",
+        _ => "right",
+    }
+}
@@ -0,0 +1,72 @@
+use gtk::prelude::*;
+use gtk::{cairo, glib};
+use log::warn;
+
+use crate::layout;
+
+/// Style class for widgets that keep receiving pointer input while their
+/// window is click-through
+pub const INTERACTIVE_CLASS: &str = "interactive";
+
+/// Mark a widget as interactive in click-through windows
+pub fn set_interactive(widget: &impl IsA<gtk::Widget>, interactive: bool) {
+    let style = widget.style_context();
+    if interactive {
+        style.add_class(INTERACTIVE_CLASS);
+    } else {
+        style.remove_class(INTERACTIVE_CLASS);
+    }
+    // Let a click-through window pick up the new region
+    widget.queue_resize();
+}
+
+/// Let pointer input pass through `window` except over its interactive
+/// widgets. The region follows the layout until the returned handler is
+/// passed to `disable_click_through`.
+pub fn enable_click_through(window: &gtk::Window) -> glib::SignalHandlerId {
+    update_input_region(window);
+    window.connect_size_allocate(|window, _| update_input_region(window))
+}
+
+/// Restore normal pointer input on a click-through window
+pub fn disable_click_through(window: &gtk::Window, handler: glib::SignalHandlerId) {
+    window.disconnect(handler);
+    window.input_shape_combine_region(None);
+}
+
+// Limit the input shape to the interactive widgets
+fn update_input_region(window: &gtk::Window) {
+    // Windows are dragged by any point while the layout is edited
+    if layout::is_editing() {
+        window.input_shape_combine_region(None);
+        return;
+    }
+
+    let region = cairo::Region::create();
+    add_interactive(window.upcast_ref(), window, &region);
+    window.input_shape_combine_region(Some(&region));
+}
+
+// Add the areas of interactive widgets at or below `widget` to the region
+fn add_interactive(widget: &gtk::Widget, window: &gtk::Window, region: &cairo::Region) {
+    if !widget.is_visible() {
+        return;
+    }
+
+    if widget.style_context().has_class(INTERACTIVE_CLASS) {
+        let allocation = widget.allocation();
+        if let Some((x, y)) = widget.translate_coordinates(window, 0, 0) {
+            let area = cairo::RectangleInt::new(x, y, allocation.width(), allocation.height());
+            if let Err(e) = region.union_rectangle(&area) {
+                warn!("Failed to update input region: {}", e);
+            }
+        }
+        return;
+    }
+
+    if let Some(container) = widget.downcast_ref::<gtk::Container>() {
+        for child in container.children() {
+            add_interactive(&child, window, region);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use gtk::prelude::*;
+use gtk::{EventSequenceState, GestureDrag, PropagationPhase};
+use gtk_layer_shell::{Edge, LayerShell};
+use log::info;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::Once;
+
+use crate::config::{self, Position};
+
+// Style class outlining widgets while the layout is edited
+const EDITING_CLASS: &str = "layout-editing";
+
+thread_local! {
+    static EDITING: Cell<bool> = const { Cell::new(false) };
+
+    // Draggable windows, with the gestures that move them
+    static DRAGGABLES: RefCell<Vec<(gtk::Window, GestureDrag)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Whether edit-layout mode is on
+pub fn is_editing() -> bool {
+    EDITING.with(Cell::get)
+}
+
+/// Turn edit-layout mode on or off for every draggable window
+pub fn set_editing(editing: bool) {
+    info!("Edit layout mode {}", if editing { "on" } else { "off" });
+    EDITING.with(|current| current.set(editing));
+    DRAGGABLES.with(|draggables| {
+        for (window, _) in draggables.borrow().iter() {
+            show_editing(window, editing);
+        }
+    });
+}
+
+pub fn toggle_editing() {
+    set_editing(!is_editing());
+}
+
+/// Let `window` be dragged with the mouse in edit-layout mode, moving it by
+/// the margins of its anchored edges. `on_moved(x, y)` is called with the
+/// horizontal and vertical margins when a drag ends.
+pub fn make_draggable(window: &gtk::Window, on_moved: impl Fn(i32, i32) + 'static) {
+    load_css();
+
+    // Capture the press before buttons inside the window can take it
+    let gesture = GestureDrag::new(window);
+    gesture.set_propagation_phase(PropagationPhase::Capture);
+    gesture.connect_drag_begin(|gesture, _, _| {
+        let state = if is_editing() {
+            EventSequenceState::Claimed
+        } else {
+            EventSequenceState::Denied
+        };
+        gesture.set_state(state);
+    });
+
+    // Offsets are relative to where the drag started on the window, which
+    // moves with the pointer, so each update is a step from the last one
+    {
+        let window = window.clone();
+        gesture.connect_drag_update(move |_, dx, dy| {
+            if is_editing() {
+                move_by(&window, dx as i32, dy as i32);
+            }
+        });
+    }
+    {
+        let window = window.clone();
+        gesture.connect_drag_end(move |_, _, _| {
+            if is_editing() {
+                let (x, y) = position(&window);
+                on_moved(x, y);
+            }
+        });
+    }
+
+    window.connect_destroy(|window| {
+        DRAGGABLES.with(|draggables| {
+            draggables
+                .borrow_mut()
+                .retain(|(draggable, _)| draggable != window)
+        });
+    });
+    if is_editing() {
+        show_editing(window, true);
+    }
+    DRAGGABLES.with(|draggables| draggables.borrow_mut().push((window.clone(), gesture)));
+}
+
+/// Put a window at a saved position, the margins of its anchored edges
+pub fn move_to(window: &gtk::Window, x: i32, y: i32) {
+    let (horizontal, vertical) = drag_edges(window);
+    if let Some((edge, _)) = horizontal {
+        window.set_layer_shell_margin(edge, x);
+    }
+    if let Some((edge, _)) = vertical {
+        window.set_layer_shell_margin(edge, y);
+    }
+}
+
+// An edge whose margin follows the pointer, and the sign of the change
+type DragEdge = Option<(Edge, i32)>;
+
+// The edges whose margins move a window horizontally and vertically, with
+// the direction a positive pointer offset changes them. Centered axes
+// cannot be dragged.
+fn drag_edges(window: &gtk::Window) -> (DragEdge, DragEdge) {
+    let along = |start: Edge, end: Edge| {
+        if window.is_anchor(start) {
+            Some((start, 1))
+        } else if window.is_anchor(end) {
+            Some((end, -1))
+        } else {
+            None
+        }
+    };
+    (
+        along(Edge::Left, Edge::Right),
+        along(Edge::Top, Edge::Bottom),
+    )
+}
+
+fn move_by(window: &gtk::Window, dx: i32, dy: i32) {
+    let (horizontal, vertical) = drag_edges(window);
+    for (edge, delta) in [(horizontal, dx), (vertical, dy)]
+        .into_iter()
+        .filter_map(|(edge, delta)| edge.map(|(edge, sign)| (edge, sign * delta)))
+    {
+        let margin = window.layer_shell_margin(edge) + delta;
+        window.set_layer_shell_margin(edge, margin.max(0));
+    }
+}
+
+fn position(window: &gtk::Window) -> (i32, i32) {
+    let (horizontal, vertical) = drag_edges(window);
+    let margin = |edge: DragEdge| edge.map_or(0, |(edge, _)| window.layer_shell_margin(edge));
+    (margin(horizontal), margin(vertical))
+}
+
+// Outline the window contents while editing and take clicks anywhere on it
+fn show_editing(window: &gtk::Window, editing: bool) {
+    if let Some(child) = window.child() {
+        let style = child.style_context();
+        if editing {
+            style.add_class(EDITING_CLASS);
+        } else {
+            style.remove_class(EDITING_CLASS);
+        }
+    }
+    // Click-through windows pick their input region up on the next layout
+    window.queue_resize();
+}
+
+fn load_css() {
+    static CSS: Once = Once::new();
+    CSS.call_once(|| {
+        let Some(screen) = gtk::gdk::Screen::default() else {
+            return;
+        };
+        let provider = gtk::CssProvider::new();
+        if provider
+            .load_from_data(b".layout-editing { box-shadow: inset 0 0 0 2px #3584e4; }")
+            .is_ok()
+        {
+            gtk::StyleContext::add_provider_for_screen(
+                &screen,
+                &provider,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+            );
+        }
+    });
+}
+
+/// Saved positions of draggable script windows, keyed by script and window title
+pub struct PositionStore {
+    positions: RefCell<HashMap<String, Position>>,
+    persist: bool,
+}
+
+impl PositionStore {
+    /// Start from `positions`, writing changes back to the config file if
+    /// `persist` is set
+    pub fn new(positions: HashMap<String, Position>, persist: bool) -> Self {
+        PositionStore {
+            positions: RefCell::new(positions),
+            persist,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Position> {
+        self.positions.borrow().get(key).copied()
+    }
+
+    pub fn set(&self, key: &str, position: Position) {
+        self.positions
+            .borrow_mut()
+            .insert(key.to_string(), position);
+        if self.persist {
+            config::update_config(|config| {
+                config.scripts.positions.insert(key.to_string(), position);
+            });
+        }
+    }
+}
@@ -1,26 +1,124 @@
-use gtk::Application;
 use gtk::prelude::*;
+use gtk::{Application, gio, glib};
 use log::{error, info};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
+mod backend;
 mod calendar;
+mod cli;
 mod config;
+mod dbus;
+mod desktop;
 mod dock;
+mod fs;
+mod harness;
+mod input;
+mod layout;
+mod outputs;
+mod preview;
+mod render;
 mod script;
+mod sway;
+mod tasks;
+mod visibility;
+
+const APP_ID: &str = "com.example.sway_widgets";
 
 fn main() {
     env_logger::init();
 
+    match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Run) => {}
+        Ok(cli::Command::Test(scripts)) => {
+            let passed = harness::run(&scripts);
+            std::process::exit(if passed { 0 } else { 1 });
+        }
+        Ok(cli::Command::Render {
+            widget,
+            out,
+            size,
+            date,
+        }) => {
+            if let Err(e) = render::render(&widget, &out, size, date) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(cli::Command::EditLayout) => {
+            if let Err(e) = send_action("edit-layout", None) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(cli::Command::Visibility { action, name }) => {
+            if let Err(e) = send_action(&action, Some(&name.to_variant())) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    }
+
     // Load configuration
     let config = config::load_config();
     info!("Configuration loaded: {:?}", config);
 
-    let app = Application::builder()
-        .application_id("com.example.sway_widgets")
-        .build();
+    let app = Application::builder().application_id(APP_ID).build();
+
+    // Toggled from `swaydgets edit-layout` through the application's D-Bus actions
+    let edit_layout = gio::SimpleAction::new("edit-layout", None);
+    edit_layout.connect_activate(|_, _| layout::toggle_editing());
+    app.add_action(&edit_layout);
+
+    // Named widgets are shown and hidden by `swaydgets show|hide|toggle <name>`
+    for action in ["show", "hide", "toggle"] {
+        let visibility_action = gio::SimpleAction::new(action, Some(glib::VariantTy::STRING));
+        visibility_action.connect_activate(move |_, parameter| {
+            let Some(name) = parameter.and_then(|parameter| parameter.str()) else {
+                return;
+            };
+            let result = match action {
+                "show" => visibility::set_visible(name, true),
+                "hide" => visibility::set_visible(name, false),
+                _ => visibility::toggle(name).map(|_| ()),
+            };
+            if let Err(e) = result {
+                error!("Failed to {} widget: {}", action, e);
+            }
+        });
+        app.add_action(&visibility_action);
+    }
+
+    // Widgets hidden in the config stay hidden until shown
+    if !config.calendar.visible {
+        visibility::hide_at_start(["calendar".to_string()]);
+    }
+    visibility::hide_at_start(config.scripts.hidden.iter().cloned());
 
     info!("Starting Sway widgets application");
 
+    // Scripts register timers that need the Lua state to outlive activation
+    let script_manager = Rc::new(RefCell::new(None));
+
+    // Activation repeats when another instance starts, keep one set of widgets
+    let activated = Cell::new(false);
+
     app.connect_activate(move |app| {
+        if activated.replace(true) {
+            return;
+        }
+
         // Create the calendar widget if enabled
         if config.calendar.enabled {
             calendar::create_calendar(app, &config.calendar);
@@ -32,11 +130,32 @@ fn main() {
         }
 
         // Load Lua scripts for custom widgets
-        let mut script_manager = script::ScriptManager::new(&app);
-        if let Err(e) = script_manager.load_scripts() {
+        let manager = Rc::new(script::ScriptManager::new(app, &config.scripts));
+        if let Err(e) = manager.load_scripts() {
             error!("Failed to load scripts: {}", e);
         }
+        manager.watch_scripts();
+        *script_manager.borrow_mut() = Some(manager);
     });
 
     app.run();
 }
+
+// Activate an action in the running instance
+fn send_action(action: &str, parameter: Option<&glib::Variant>) -> Result<(), String> {
+    let app = gio::Application::new(Some(APP_ID), gio::ApplicationFlags::empty());
+    app.register(gio::Cancellable::NONE)
+        .map_err(|e| format!("Failed to reach swaydgets: {}", e))?;
+    if !app.is_remote() {
+        return Err("swaydgets is not running".to_string());
+    }
+
+    app.activate_action(action, parameter);
+    // Actions are sent asynchronously, flush before exiting
+    if let Some(connection) = app.dbus_connection() {
+        connection
+            .flush_sync(gio::Cancellable::NONE)
+            .map_err(|e| format!("Failed to send {}: {}", action, e))?;
+    }
+    Ok(())
+}
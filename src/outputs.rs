@@ -0,0 +1,506 @@
+use gtk::Orientation;
+use gtk::gdk;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk_layer_shell::{Edge, Layer, LayerShell};
+use log::{info, warn};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::backend::{Backend, BoxHandle, LabelHandle, MoveCallback, Watch, WindowHandle};
+use crate::sway;
+use crate::visibility::Transition;
+
+/// Output selector matching every monitor
+pub const ALL_OUTPUTS: &str = "all";
+
+/// Whether an `output` option selects the output called `name`
+pub fn selects(selector: &str, name: &str) -> bool {
+    selector == ALL_OUTPUTS || selector == name
+}
+
+/// The connected monitors with their output names, such as `DP-1`
+pub fn monitors() -> Vec<(String, gdk::Monitor)> {
+    let Some(display) = gdk::Display::default() else {
+        return Vec::new();
+    };
+
+    // GDK only knows the geometry, Sway knows which output is where
+    let sway_outputs = match sway::get_outputs() {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            warn!("Failed to get outputs from Sway: {}", e);
+            Vec::new()
+        }
+    };
+
+    (0..display.n_monitors())
+        .filter_map(|index| display.monitor(index))
+        .enumerate()
+        .map(|(index, monitor)| {
+            let geometry = monitor.geometry();
+            let name = sway_outputs
+                .iter()
+                .find(|output| {
+                    output.active
+                        && output.rect.x == geometry.x()
+                        && output.rect.y == geometry.y()
+                        && output.rect.width == geometry.width()
+                        && output.rect.height == geometry.height()
+                })
+                .map(|output| output.name.clone())
+                .or_else(|| monitor.model().map(|model| model.to_string()))
+                .unwrap_or_else(|| format!("monitor-{}", index));
+            (name, monitor)
+        })
+        .collect()
+}
+
+/// Put a layer shell window on `monitor`, or leave the choice to the compositor
+pub fn place(window: &impl IsA<gtk::Window>, monitor: Option<&gdk::Monitor>) {
+    if let Some(monitor) = monitor {
+        window.set_monitor(monitor);
+    }
+}
+
+/// Build a widget's windows once per monitor selected by `output`, adding and
+/// closing instances as monitors are plugged in and out. Without an output
+/// the windows are built once and the compositor picks the monitor.
+///
+/// `build` gets the monitor along with its output name, as in `monitors`.
+pub fn instantiate(
+    output: Option<&str>,
+    build: impl Fn(Option<&gdk::Monitor>, Option<&str>) -> Vec<gtk::Window> + 'static,
+) {
+    let Some(selector) = output.map(str::to_string) else {
+        build(None, None);
+        return;
+    };
+    let Some(display) = gdk::Display::default() else {
+        warn!("No display to find outputs on, placing widget anywhere");
+        build(None, None);
+        return;
+    };
+
+    let instances: RefCell<Vec<(gdk::Monitor, Vec<gtk::Window>)>> = RefCell::new(Vec::new());
+    let sync = Rc::new(move || {
+        let monitors = monitors();
+
+        // Close instances whose monitor went away
+        instances.borrow_mut().retain(|(monitor, windows)| {
+            let present = monitors.iter().any(|(_, other)| other == monitor);
+            if !present {
+                for window in windows {
+                    window.close();
+                }
+            }
+            present
+        });
+
+        for (name, monitor) in monitors {
+            let placed = instances
+                .borrow()
+                .iter()
+                .any(|(other, _)| *other == monitor);
+            if selects(&selector, &name) && !placed {
+                info!("Placing widget on output {}", name);
+                let windows = build(Some(&monitor), Some(&name));
+                instances.borrow_mut().push((monitor, windows));
+            }
+        }
+    });
+    sync();
+
+    // Wait for GDK to finish updating its monitor list before syncing
+    let on_change = Rc::new(move || {
+        let sync = sync.clone();
+        glib::idle_add_local_once(move || sync());
+    });
+    {
+        let on_change = on_change.clone();
+        display.connect_monitor_added(move |_, _| on_change());
+    }
+    display.connect_monitor_removed(move |_, _| on_change());
+}
+
+/// A script window shown on every output matching a selector. Calls are
+/// applied to each instance and replayed when a matching output appears.
+pub struct OutputWindow {
+    backend: Rc<dyn Backend>,
+    title: String,
+    width: i32,
+    height: i32,
+    selector: String,
+    instances: RefCell<Vec<(String, Rc<dyn WindowHandle>)>>,
+    state: RefCell<WindowState>,
+    boxes: RefCell<Vec<Rc<OutputBox>>>,
+    closed: Cell<bool>,
+    outputs_watch: RefCell<Option<Box<dyn Watch>>>,
+}
+
+// Window settings replayed on new instances
+#[derive(Default)]
+struct WindowState {
+    margins: Vec<(Edge, i32)>,
+    anchors: Vec<(Edge, bool)>,
+    layer: Option<Layer>,
+    exclusive_zone: Option<i32>,
+    namespace: Option<String>,
+    click_through: bool,
+    transition: Option<Transition>,
+    draggable: Option<Rc<DragState>>,
+    visible: bool,
+}
+
+// Where a draggable window was last moved to, shared by its instances
+struct DragState {
+    position: Cell<Option<(i32, i32)>>,
+    on_moved: MoveCallback,
+}
+
+impl WindowState {
+    fn apply(&self, window: &dyn WindowHandle) {
+        if let Some(namespace) = &self.namespace {
+            window.set_namespace(namespace);
+        }
+        if let Some(layer) = self.layer {
+            window.set_layer(layer);
+        }
+        for &(edge, anchored) in &self.anchors {
+            window.set_anchor(edge, anchored);
+        }
+        for &(edge, margin) in &self.margins {
+            window.set_margin(edge, margin);
+        }
+        if let Some(zone) = self.exclusive_zone {
+            window.set_exclusive_zone(zone);
+        }
+        if self.click_through {
+            window.set_click_through(true);
+        }
+        if let Some(transition) = self.transition {
+            window.set_transition(transition);
+        }
+        if let Some(drag) = &self.draggable {
+            let drag = drag.clone();
+            window.make_draggable(
+                drag.position.get(),
+                Box::new(move |x, y| {
+                    drag.position.set(Some((x, y)));
+                    (drag.on_moved)(x, y);
+                }),
+            );
+        }
+    }
+}
+
+// Remember the latest value set for an edge
+fn set_edge<T>(values: &mut Vec<(Edge, T)>, edge: Edge, value: T) {
+    values.retain(|(other, _)| *other != edge);
+    values.push((edge, value));
+}
+
+impl OutputWindow {
+    pub fn new(
+        backend: Rc<dyn Backend>,
+        title: &str,
+        width: i32,
+        height: i32,
+        selector: &str,
+    ) -> Rc<Self> {
+        let window = Rc::new(OutputWindow {
+            backend: backend.clone(),
+            title: title.to_string(),
+            width,
+            height,
+            selector: selector.to_string(),
+            instances: RefCell::new(Vec::new()),
+            state: RefCell::new(WindowState::default()),
+            boxes: RefCell::new(Vec::new()),
+            closed: Cell::new(false),
+            outputs_watch: RefCell::new(None),
+        });
+
+        let weak = Rc::downgrade(&window);
+        match backend.watch_outputs(Box::new(move || {
+            if let Some(window) = weak.upgrade() {
+                window.sync();
+            }
+        })) {
+            Ok(watch) => *window.outputs_watch.borrow_mut() = Some(watch),
+            Err(e) => warn!("Failed to watch outputs for {}: {}", title, e),
+        }
+
+        window.sync();
+        window
+    }
+
+    // Match the instances to the outputs currently selected
+    fn sync(&self) {
+        if self.closed.get() {
+            return;
+        }
+        let outputs: Vec<String> = self
+            .backend
+            .outputs()
+            .into_iter()
+            .filter(|name| selects(&self.selector, name))
+            .collect();
+
+        let removed: Vec<String> = self
+            .instances
+            .borrow()
+            .iter()
+            .map(|(output, _)| output.clone())
+            .filter(|output| !outputs.contains(output))
+            .collect();
+        for output in removed {
+            self.remove_instance(&output);
+        }
+
+        for output in outputs {
+            let exists = self
+                .instances
+                .borrow()
+                .iter()
+                .any(|(other, _)| *other == output);
+            if !exists {
+                self.add_instance(&output);
+            }
+        }
+    }
+
+    fn add_instance(&self, output: &str) {
+        info!("Showing {} on output {}", self.title, output);
+        let window = self
+            .backend
+            .create_window(&self.title, self.width, self.height, Some(output));
+        self.state.borrow().apply(&*window);
+        for container in self.boxes.borrow().iter() {
+            container.instantiate(output, &*window);
+        }
+        if self.state.borrow().visible {
+            window.show();
+        }
+        self.instances
+            .borrow_mut()
+            .push((output.to_string(), window));
+    }
+
+    fn remove_instance(&self, output: &str) {
+        info!("Removing {} from output {}", self.title, output);
+        self.instances.borrow_mut().retain(|(other, window)| {
+            if other == output {
+                window.close();
+            }
+            other != output
+        });
+        for container in self.boxes.borrow().iter() {
+            container.remove(output);
+        }
+    }
+
+    fn each(&self, apply: impl Fn(&dyn WindowHandle)) {
+        for (_, window) in self.instances.borrow().iter() {
+            apply(&**window);
+        }
+    }
+}
+
+impl WindowHandle for OutputWindow {
+    fn set_margin(&self, edge: Edge, margin: i32) {
+        set_edge(&mut self.state.borrow_mut().margins, edge, margin);
+        self.each(|window| window.set_margin(edge, margin));
+    }
+
+    fn set_layer(&self, layer: Layer) {
+        self.state.borrow_mut().layer = Some(layer);
+        self.each(|window| window.set_layer(layer));
+    }
+
+    fn set_anchor(&self, edge: Edge, anchored: bool) {
+        set_edge(&mut self.state.borrow_mut().anchors, edge, anchored);
+        self.each(|window| window.set_anchor(edge, anchored));
+    }
+
+    fn set_exclusive_zone(&self, zone: i32) {
+        self.state.borrow_mut().exclusive_zone = Some(zone);
+        self.each(|window| window.set_exclusive_zone(zone));
+    }
+
+    fn set_namespace(&self, namespace: &str) {
+        self.state.borrow_mut().namespace = Some(namespace.to_string());
+        self.each(|window| window.set_namespace(namespace));
+    }
+
+    fn set_click_through(&self, enabled: bool) {
+        self.state.borrow_mut().click_through = enabled;
+        self.each(|window| window.set_click_through(enabled));
+    }
+
+    fn make_draggable(&self, position: Option<(i32, i32)>, on_moved: MoveCallback) {
+        let drag = Rc::new(DragState {
+            position: Cell::new(position),
+            on_moved,
+        });
+        self.state.borrow_mut().draggable = Some(drag.clone());
+        self.each(|window| {
+            let drag = drag.clone();
+            window.make_draggable(
+                position,
+                Box::new(move |x, y| {
+                    drag.position.set(Some((x, y)));
+                    (drag.on_moved)(x, y);
+                }),
+            );
+        });
+    }
+
+    fn set_transition(&self, transition: Transition) {
+        self.state.borrow_mut().transition = Some(transition);
+        self.each(|window| window.set_transition(transition));
+    }
+
+    fn show(&self) {
+        self.state.borrow_mut().visible = true;
+        self.each(|window| window.show());
+    }
+
+    fn hide(&self) {
+        self.state.borrow_mut().visible = false;
+        self.each(|window| window.hide());
+    }
+
+    fn close(&self) {
+        self.closed.set(true);
+        self.outputs_watch.take();
+        for (_, window) in self.instances.take() {
+            window.close();
+        }
+    }
+
+    fn add_box(&self, orientation: Orientation, spacing: i32) -> Rc<dyn BoxHandle> {
+        let container = Rc::new(OutputBox {
+            orientation,
+            spacing,
+            instances: RefCell::new(Vec::new()),
+            css: RefCell::new(Vec::new()),
+            interactive: Cell::new(false),
+            labels: RefCell::new(Vec::new()),
+        });
+        for (output, window) in self.instances.borrow().iter() {
+            container.instantiate(output, &**window);
+        }
+        self.boxes.borrow_mut().push(container.clone());
+        container
+    }
+}
+
+// A box in every instance of an output window
+struct OutputBox {
+    orientation: Orientation,
+    spacing: i32,
+    instances: RefCell<Vec<(String, Rc<dyn BoxHandle>)>>,
+    css: RefCell<Vec<String>>,
+    interactive: Cell<bool>,
+    labels: RefCell<Vec<Rc<OutputLabel>>>,
+}
+
+impl OutputBox {
+    fn instantiate(&self, output: &str, window: &dyn WindowHandle) {
+        let container = window.add_box(self.orientation, self.spacing);
+        for css in self.css.borrow().iter() {
+            container.set_css(css);
+        }
+        if self.interactive.get() {
+            container.set_interactive(true);
+        }
+        for label in self.labels.borrow().iter() {
+            label.instantiate(output, &*container);
+        }
+        self.instances
+            .borrow_mut()
+            .push((output.to_string(), container));
+    }
+
+    fn remove(&self, output: &str) {
+        self.instances
+            .borrow_mut()
+            .retain(|(other, _)| other != output);
+        for label in self.labels.borrow().iter() {
+            label.remove(output);
+        }
+    }
+}
+
+impl BoxHandle for OutputBox {
+    fn add_label(&self, text: &str, font_size: i32) -> Rc<dyn LabelHandle> {
+        let label = Rc::new(OutputLabel {
+            text: RefCell::new(text.to_string()),
+            font_size,
+            instances: RefCell::new(Vec::new()),
+            css: RefCell::new(Vec::new()),
+        });
+        for (output, container) in self.instances.borrow().iter() {
+            label.instantiate(output, &**container);
+        }
+        self.labels.borrow_mut().push(label.clone());
+        label
+    }
+
+    fn set_css(&self, css: &str) {
+        self.css.borrow_mut().push(css.to_string());
+        for (_, container) in self.instances.borrow().iter() {
+            container.set_css(css);
+        }
+    }
+
+    fn set_interactive(&self, interactive: bool) {
+        self.interactive.set(interactive);
+        for (_, container) in self.instances.borrow().iter() {
+            container.set_interactive(interactive);
+        }
+    }
+}
+
+// A label in every instance of an output window
+struct OutputLabel {
+    text: RefCell<String>,
+    font_size: i32,
+    instances: RefCell<Vec<(String, Rc<dyn LabelHandle>)>>,
+    css: RefCell<Vec<String>>,
+}
+
+impl OutputLabel {
+    fn instantiate(&self, output: &str, container: &dyn BoxHandle) {
+        let label = container.add_label(&self.text.borrow(), self.font_size);
+        for css in self.css.borrow().iter() {
+            label.set_css(css);
+        }
+        self.instances
+            .borrow_mut()
+            .push((output.to_string(), label));
+    }
+
+    fn remove(&self, output: &str) {
+        self.instances
+            .borrow_mut()
+            .retain(|(other, _)| other != output);
+    }
+}
+
+impl LabelHandle for OutputLabel {
+    fn set_text(&self, text: &str) {
+        *self.text.borrow_mut() = text.to_string();
+        for (_, label) in self.instances.borrow().iter() {
+            label.set_text(text);
+        }
+    }
+
+    fn set_css(&self, css: &str) {
+        self.css.borrow_mut().push(css.to_string());
+        for (_, label) in self.instances.borrow().iter() {
+            label.set_css(css);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use gtk::gdk;
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::{gio, glib};
+use log::{debug, info, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsStr;
+use std::rc::Rc;
+use std::time::Duration;
+
+// Width previews are scaled to, keeping their aspect ratio
+pub const PREVIEW_WIDTH: i32 = 240;
+
+/// A window to take a preview of
+#[derive(Clone, Debug)]
+pub struct PreviewTarget {
+    pub id: i64,
+    /// Geometry in layout coordinates, as in the Sway tree
+    pub rect: gdk::Rectangle,
+    /// Whether the window is on a workspace shown on some output, so a
+    /// capture of its rect shows it
+    pub on_screen: bool,
+}
+
+pub type CaptureCallback = Box<dyn FnOnce(Result<Pixbuf, String>)>;
+
+/// Somewhere window pictures come from
+pub trait PreviewSource {
+    fn name(&self) -> &str;
+
+    /// Whether the source can work here, checked once
+    fn available(&self) -> bool;
+
+    /// Take a picture of the window, calling `done` on the GTK loop
+    fn capture(&self, target: &PreviewTarget, done: CaptureCallback);
+}
+
+/// Captures a window's rect from the screen with grim, which uses the
+/// wlroots screencopy protocol
+pub struct GrimSource;
+
+impl PreviewSource for GrimSource {
+    fn name(&self) -> &str {
+        "grim"
+    }
+
+    fn available(&self) -> bool {
+        glib::find_program_in_path("grim").is_some()
+    }
+
+    fn capture(&self, target: &PreviewTarget, done: CaptureCallback) {
+        let rect = target.rect;
+        let geometry = format!(
+            "{},{} {}x{}",
+            rect.x(),
+            rect.y(),
+            rect.width(),
+            rect.height()
+        );
+        let argv = ["grim", "-g", &geometry, "-t", "png", "-"].map(OsStr::new);
+        let process = match gio::Subprocess::newv(&argv, gio::SubprocessFlags::STDOUT_PIPE) {
+            Ok(process) => process,
+            Err(e) => {
+                done(Err(format!("Failed to run grim: {}", e)));
+                return;
+            }
+        };
+
+        let finished = process.clone();
+        process.communicate_async(None, None::<&gio::Cancellable>, move |result| {
+            let png = match result {
+                Ok((Some(png), _)) if finished.is_successful() => png,
+                Ok(_) => {
+                    done(Err("grim failed to capture the window".to_string()));
+                    return;
+                }
+                Err(e) => {
+                    done(Err(format!("Failed to read from grim: {}", e)));
+                    return;
+                }
+            };
+            let stream = gio::MemoryInputStream::from_bytes(&png);
+            done(
+                Pixbuf::from_stream_at_scale(
+                    &stream,
+                    PREVIEW_WIDTH,
+                    -1,
+                    true,
+                    None::<&gio::Cancellable>,
+                )
+                .map_err(|e| format!("Failed to load grim capture: {}", e)),
+            );
+        });
+    }
+}
+
+// How long a source gets to deliver a picture before the next one is tried
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type RefreshCallback = Box<dyn FnOnce(Option<Pixbuf>)>;
+
+/// Window pictures for the dock's hover popovers. Sources are tried in
+/// order and the last picture of each window is kept, so windows that are
+/// no longer on screen still show how they looked.
+pub struct Previews {
+    sources: Vec<Box<dyn PreviewSource>>,
+    targets: RefCell<HashMap<i64, PreviewTarget>>,
+    cache: RefCell<HashMap<i64, Pixbuf>>,
+    // One capture at a time: the window being captured, the windows
+    // waiting for their turn, and the callbacks waiting on each window
+    capturing: Cell<Option<i64>>,
+    queue: RefCell<VecDeque<i64>>,
+    waiting: RefCell<HashMap<i64, Vec<RefreshCallback>>>,
+}
+
+impl Previews {
+    /// Use the sources that are available, in order
+    pub fn new(sources: Vec<Box<dyn PreviewSource>>) -> Rc<Self> {
+        let sources: Vec<Box<dyn PreviewSource>> = sources
+            .into_iter()
+            .filter(|source| {
+                let available = source.available();
+                if !available {
+                    info!("Window previews from {} are not available", source.name());
+                }
+                available
+            })
+            .collect();
+        Rc::new(Previews {
+            sources,
+            targets: RefCell::new(HashMap::new()),
+            cache: RefCell::new(HashMap::new()),
+            capturing: Cell::new(None),
+            queue: RefCell::new(VecDeque::new()),
+            waiting: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// The sources used unless told otherwise
+    pub fn with_default_sources() -> Rc<Self> {
+        Previews::new(vec![Box::new(GrimSource)])
+    }
+
+    /// Follow the current windows, forgetting the pictures of closed ones
+    pub fn set_targets(&self, targets: impl IntoIterator<Item = PreviewTarget>) {
+        let targets: HashMap<i64, PreviewTarget> = targets
+            .into_iter()
+            .map(|target| (target.id, target))
+            .collect();
+        self.cache
+            .borrow_mut()
+            .retain(|id, _| targets.contains_key(id));
+        *self.targets.borrow_mut() = targets;
+    }
+
+    /// The last picture of a window, if there is one
+    pub fn cached(&self, id: i64) -> Option<Pixbuf> {
+        self.cache.borrow().get(&id).cloned()
+    }
+
+    /// Take a new picture of a window if it is on screen, calling `done`
+    /// with it, or with None when no picture was taken. Windows off screen
+    /// keep their old picture. Asking again while the window is being
+    /// captured waits for the same picture.
+    pub fn refresh(self: &Rc<Self>, id: i64, done: impl FnOnce(Option<Pixbuf>) + 'static) {
+        if self.sources.is_empty() || !self.on_screen(id) {
+            done(None);
+            return;
+        }
+        self.waiting
+            .borrow_mut()
+            .entry(id)
+            .or_default()
+            .push(Box::new(done));
+        match self.capturing.get() {
+            None => self.start(id),
+            Some(current) if current == id => {}
+            Some(_) => {
+                let mut queue = self.queue.borrow_mut();
+                if !queue.contains(&id) {
+                    queue.push_back(id);
+                }
+            }
+        }
+    }
+
+    fn on_screen(&self, id: i64) -> bool {
+        self.targets
+            .borrow()
+            .get(&id)
+            .is_some_and(|target| target.on_screen)
+    }
+
+    fn start(self: &Rc<Self>, id: i64) {
+        let target = self.targets.borrow().get(&id).cloned();
+        match target {
+            Some(target) if target.on_screen => {
+                self.capturing.set(Some(id));
+                self.try_sources(target, 0);
+            }
+            _ => self.finish(id, None),
+        }
+    }
+
+    fn try_sources(self: &Rc<Self>, target: PreviewTarget, index: usize) {
+        let Some(source) = self.sources.get(index) else {
+            self.finish(target.id, None);
+            return;
+        };
+        let name = source.name().to_string();
+        let previews = Rc::downgrade(self);
+        let captured = target.clone();
+        let attempt = Attempt::new(move |result| {
+            let Some(previews) = previews.upgrade() else {
+                return;
+            };
+            match result {
+                Ok(pixbuf) => {
+                    debug!("Captured window {} with {}", target.id, name);
+                    previews.finish(target.id, Some(pixbuf));
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to capture window {} with {}: {}",
+                        target.id, name, e
+                    );
+                    // Let the GTK loop go on before trying the next source
+                    glib::idle_add_local_once(move || previews.try_sources(target, index + 1));
+                }
+            }
+        });
+
+        // Sources that never answer are given up on
+        let timeout = Rc::downgrade(&attempt);
+        glib::timeout_add_local_once(CAPTURE_TIMEOUT, move || {
+            if let Some(attempt) = timeout.upgrade() {
+                attempt.settle(Err("timed out".to_string()));
+            }
+        });
+        source.capture(&captured, Box::new(move |result| attempt.settle(result)));
+    }
+
+    // Keep a picture, hand it to everyone waiting for it and capture the
+    // next window in line
+    fn finish(self: &Rc<Self>, id: i64, pixbuf: Option<Pixbuf>) {
+        self.capturing.set(None);
+        // The window may have closed during the capture
+        if let Some(pixbuf) = &pixbuf
+            && self.targets.borrow().contains_key(&id)
+        {
+            self.cache.borrow_mut().insert(id, pixbuf.clone());
+        }
+        let waiting = self.waiting.borrow_mut().remove(&id).unwrap_or_default();
+        for done in waiting {
+            done(pixbuf.clone());
+        }
+
+        if self.capturing.get().is_none() {
+            let next = self.queue.borrow_mut().pop_front();
+            if let Some(next) = next {
+                self.start(next);
+            }
+        }
+    }
+}
+
+// One try of one source. It settles once: when the source answers, when it
+// drops its callback without answering, or when it takes too long.
+struct Attempt {
+    settle: RefCell<Option<CaptureCallback>>,
+}
+
+impl Attempt {
+    fn new(settle: impl FnOnce(Result<Pixbuf, String>) + 'static) -> Rc<Self> {
+        Rc::new(Attempt {
+            settle: RefCell::new(Some(Box::new(settle))),
+        })
+    }
+
+    fn settle(&self, result: Result<Pixbuf, String>) {
+        let settle = self.settle.borrow_mut().take();
+        if let Some(settle) = settle {
+            settle(result);
+        }
+    }
+}
+
+impl Drop for Attempt {
+    fn drop(&mut self) {
+        if let Some(settle) = self.settle.get_mut().take() {
+            settle(Err("the source gave up without an answer".to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gtk::gdk_pixbuf::Colorspace;
+
+    // How a fake source answers a capture
+    #[derive(Clone)]
+    enum Answer {
+        Picture,
+        Error,
+        // Drops the callback without calling it
+        Nothing,
+        // Keeps the callback for the test to call
+        Later(Rc<RefCell<Vec<CaptureCallback>>>),
+    }
+
+    struct FakeSource {
+        answer: Answer,
+        captures: Rc<Cell<usize>>,
+    }
+
+    impl PreviewSource for FakeSource {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn available(&self) -> bool {
+            true
+        }
+
+        fn capture(&self, _: &PreviewTarget, done: CaptureCallback) {
+            self.captures.set(self.captures.get() + 1);
+            match &self.answer {
+                Answer::Picture => done(Ok(picture())),
+                Answer::Error => done(Err("failed".to_string())),
+                Answer::Nothing => {}
+                Answer::Later(held) => held.borrow_mut().push(done),
+            }
+        }
+    }
+
+    fn picture() -> Pixbuf {
+        Pixbuf::new(Colorspace::Rgb, false, 8, 2, 2).expect("pixbuf")
+    }
+
+    fn target(id: i64, on_screen: bool) -> PreviewTarget {
+        PreviewTarget {
+            id,
+            rect: gdk::Rectangle::new(0, 0, 100, 100),
+            on_screen,
+        }
+    }
+
+    // Previews from fake sources, with the number of captures of each
+    fn previews(answers: &[Answer]) -> (Rc<Previews>, Vec<Rc<Cell<usize>>>) {
+        let captures: Vec<Rc<Cell<usize>>> = answers.iter().map(|_| Rc::default()).collect();
+        let sources = answers
+            .iter()
+            .zip(&captures)
+            .map(|(answer, captures)| {
+                Box::new(FakeSource {
+                    answer: answer.clone(),
+                    captures: captures.clone(),
+                }) as Box<dyn PreviewSource>
+            })
+            .collect();
+        (Previews::new(sources), captures)
+    }
+
+    // Refresh a window, returning where the result ends up: None while
+    // waiting, then Some(picture or None)
+    fn refresh(previews: &Rc<Previews>, id: i64) -> Rc<RefCell<Option<Option<Pixbuf>>>> {
+        let result = Rc::new(RefCell::new(None));
+        let slot = result.clone();
+        previews.refresh(id, move |pixbuf| *slot.borrow_mut() = Some(pixbuf));
+        result
+    }
+
+    // Run the main loop until nothing is left to do right away
+    fn run_pending() {
+        let context = glib::MainContext::ref_thread_default();
+        while context.iteration(false) {}
+    }
+
+    // Run a test on a main loop of its own, as tests share threads
+    fn with_main_loop(test: impl FnOnce()) {
+        glib::MainContext::new()
+            .with_thread_default(test)
+            .expect("main context");
+    }
+
+    #[test]
+    fn failed_sources_fall_back_to_the_next() {
+        with_main_loop(|| {
+            for first in [Answer::Error, Answer::Nothing] {
+                let (previews, captures) = previews(&[first, Answer::Picture]);
+                previews.set_targets([target(1, true)]);
+                let result = refresh(&previews, 1);
+                run_pending();
+                assert!(matches!(*result.borrow(), Some(Some(_))));
+                assert!(previews.cached(1).is_some());
+                assert_eq!(captures[0].get(), 1);
+                assert_eq!(captures[1].get(), 1);
+            }
+        });
+    }
+
+    #[test]
+    fn no_picture_when_every_source_fails() {
+        with_main_loop(|| {
+            let (previews, _) = previews(&[Answer::Error]);
+            previews.set_targets([target(1, true)]);
+            let result = refresh(&previews, 1);
+            run_pending();
+            assert!(matches!(*result.borrow(), Some(None)));
+
+            // A later refresh still captures
+            let result = refresh(&previews, 1);
+            run_pending();
+            assert!(matches!(*result.borrow(), Some(None)));
+        });
+    }
+
+    #[test]
+    fn windows_closed_during_a_capture_are_not_cached() {
+        with_main_loop(|| {
+            let held = Rc::new(RefCell::new(Vec::new()));
+            let (previews, _) = previews(&[Answer::Later(held.clone())]);
+            previews.set_targets([target(1, true)]);
+            refresh(&previews, 1);
+            previews.set_targets([]);
+            let done = held.borrow_mut().pop().expect("capture started");
+            done(Ok(picture()));
+            assert!(previews.cached(1).is_none());
+        });
+    }
+
+    #[test]
+    fn off_screen_windows_keep_their_picture() {
+        with_main_loop(|| {
+            let (previews, captures) = previews(&[Answer::Picture]);
+            previews.set_targets([target(1, true)]);
+            refresh(&previews, 1);
+            assert!(previews.cached(1).is_some());
+
+            previews.set_targets([target(1, false)]);
+            let result = refresh(&previews, 1);
+            assert!(matches!(*result.borrow(), Some(None)));
+            assert!(previews.cached(1).is_some());
+            assert_eq!(captures[0].get(), 1);
+        });
+    }
+
+    #[test]
+    fn refreshes_during_a_capture_share_or_wait_for_it() {
+        with_main_loop(|| {
+            let held = Rc::new(RefCell::new(Vec::new()));
+            let (previews, captures) = previews(&[Answer::Later(held.clone())]);
+            previews.set_targets([target(1, true), target(2, true)]);
+
+            // A focus change starts a capture, then the pointer hovers
+            let focus = refresh(&previews, 1);
+            let hover = refresh(&previews, 1);
+            let other = refresh(&previews, 2);
+            assert_eq!(captures[0].get(), 1);
+            assert!(hover.borrow().is_none());
+
+            let done = held.borrow_mut().remove(0);
+            done(Ok(picture()));
+            assert!(matches!(*focus.borrow(), Some(Some(_))));
+            assert!(matches!(*hover.borrow(), Some(Some(_))));
+
+            // The other window is captured next
+            assert_eq!(captures[0].get(), 2);
+            assert!(other.borrow().is_none());
+            let done = held.borrow_mut().remove(0);
+            done(Ok(picture()));
+            assert!(matches!(*other.borrow(), Some(Some(_))));
+        });
+    }
+}
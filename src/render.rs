@@ -0,0 +1,129 @@
+use chrono::NaiveDate;
+use gtk::OffscreenWindow;
+use gtk::prelude::*;
+use log::info;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::backend::GtkBackend;
+use crate::fs::FsPermissions;
+use crate::layout::PositionStore;
+use crate::script::{self, ScriptManager};
+use crate::{calendar, config, desktop, dock, preview};
+
+// The day the calendar shows unless told otherwise
+const RENDER_DATE: NaiveDate = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+
+/// Render a widget offscreen and write it to a PNG file.
+///
+/// `widget` is `calendar`, `dock`, `dock-preview` (the focused window as
+/// the dock's hover popover shows it) or the path (or name in the scripts
+/// directory) of a Lua script. Needs a display, but a virtual one such as
+/// Xvfb or a headless Sway session is enough.
+///
+/// The calendar shows `date`, or a fixed day so snapshots can be compared
+/// from one day to the next.
+pub fn render(
+    widget: &str,
+    out: &Path,
+    size: Option<(i32, i32)>,
+    date: Option<NaiveDate>,
+) -> Result<(), String> {
+    gtk::init().map_err(|e| format!("Failed to initialize GTK: {}", e))?;
+    let config = config::read_config().unwrap_or_default();
+
+    // Keeps the Lua state alive until the script window is drawn
+    let mut _script_manager = None;
+
+    let window = match widget {
+        "calendar" => {
+            let size = config.calendar.size;
+            let window = offscreen_window(size.width, size.height);
+            let date = date.unwrap_or(RENDER_DATE);
+            window.add(&calendar::build_calendar(move || date));
+            window
+        }
+        "dock" => {
+            let orientation = dock::dock_orientation(config.dock.edge);
+            let style = dock::DockStyle::new(&config.dock);
+            let (width, height) = dock::dock_size(orientation, &style);
+            let window = offscreen_window(width, height);
+            let dock_box = dock::build_dock_box(orientation, &style);
+            desktop::set_overrides(&config.dock.apps);
+            let items = dock::dock_items(
+                &dock::snapshot_windows(),
+                &config.dock.pinned,
+                config.dock.group_windows,
+                &dock::visible_workspaces(),
+            );
+            dock::DockView::new(&dock_box, orientation, style, Rc::new(|_, _| {})).sync(&items);
+            window.add(&dock_box);
+            window
+        }
+        "dock-preview" => {
+            let preview = dock::focused_window_preview(Duration::from_secs(5))?;
+            let window = offscreen_window(preview::PREVIEW_WIDTH, 1);
+            window.add(&preview);
+            window
+        }
+        script => {
+            let backend = Rc::new(GtkBackend::offscreen());
+            let manager = ScriptManager::with_backend(
+                backend.clone(),
+                FsPermissions::new(&config.scripts.fs_allow),
+                PositionStore::new(config.scripts.positions.clone(), false),
+            );
+            manager
+                .load_script(&script_path(script))
+                .map_err(|e| format!("Failed to load script: {}", e))?;
+            _script_manager = Some(manager);
+            backend
+                .offscreen_windows()
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("Script {} did not create a window", script))?
+        }
+    };
+
+    if let Some((width, height)) = size {
+        window.set_size_request(width, height);
+    }
+    window.show_all();
+
+    // Let GTK lay out and draw the window
+    while gtk::events_pending() {
+        gtk::main_iteration();
+    }
+
+    let pixbuf = window
+        .pixbuf()
+        .ok_or_else(|| "Widget was not drawn".to_string())?;
+    pixbuf
+        .savev(out, "png", &[])
+        .map_err(|e| format!("Failed to write {}: {}", out.display(), e))?;
+
+    info!("Rendered {} to {}", widget, out.display());
+    Ok(())
+}
+
+// Create an offscreen window of the given default size
+fn offscreen_window(width: i32, height: i32) -> OffscreenWindow {
+    let window = OffscreenWindow::new();
+    window.set_default_size(width, height);
+    window
+}
+
+// Resolve a script argument, falling back to the scripts directory
+fn script_path(script: &str) -> PathBuf {
+    let path = PathBuf::from(script);
+    if path.exists() {
+        return path;
+    }
+
+    let mut in_scripts_dir = script::scripts_dir().join(script);
+    if in_scripts_dir.extension().is_none() {
+        in_scripts_dir.set_extension("lua");
+    }
+    in_scripts_dir
+}
@@ -1,328 +1,498 @@
-use gtk::prelude::*;
-use gtk::{Application, ApplicationWindow, Box as GtkBox, Label, Orientation};
-use gtk_layer_shell::{Edge, Layer, LayerShell};
-use log::{debug, error, info};
-use mlua::{Lua, Value};
+use gtk::Application;
+use gtk::Orientation;
+use gtk_layer_shell::{Edge, Layer};
+use log::{error, info};
+use mlua::{Function, Lua, Table, Value};
 use serde_json::Value as JsonValue;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
-
-/// A structure to hold the GTK widget created by a Lua script
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use crate::backend::{
+    Backend, BoxHandle, ExecOutput, GtkBackend, HttpResponse, LabelHandle, Watch, WindowHandle,
+};
+use crate::config::{Position, ScriptsConfig};
+use crate::dbus;
+use crate::fs::{self, FsPermissions};
+use crate::layout::PositionStore;
+use crate::outputs::OutputWindow;
+use crate::sway;
+use crate::tasks::{self, Pending};
+use crate::visibility::{self, Toggle, Transition};
+
+/// A structure to hold the widget created by a Lua script
 pub struct LuaWidget {
-    window: Option<ApplicationWindow>,
+    path: PathBuf,
+    windows: Vec<Rc<dyn WindowHandle>>,
+    watches: Vec<Box<dyn Watch>>,
     update_interval: u64,
+    running: bool,
+}
+
+/// Handles shared by every callback a script registers
+#[derive(Clone)]
+pub struct ScriptContext {
+    lua: Weak<Lua>,
+    backend: Rc<dyn Backend>,
+    permissions: Rc<FsPermissions>,
+    positions: Rc<PositionStore>,
+    widget: Rc<RefCell<LuaWidget>>,
+}
+
+impl ScriptContext {
+    /// The Lua state, or None once the script has been unloaded
+    pub fn lua(&self) -> Option<Rc<Lua>> {
+        if self.widget.borrow().running {
+            self.lua.upgrade()
+        } else {
+            None
+        }
+    }
+
+    pub fn backend(&self) -> &Rc<dyn Backend> {
+        &self.backend
+    }
+
+    pub fn permissions(&self) -> &FsPermissions {
+        &self.permissions
+    }
+
+    /// Keep a file watch or subscription alive until the script is unloaded
+    pub fn keep_watch(&self, watch: Box<dyn Watch>) {
+        self.widget.borrow_mut().watches.push(watch);
+    }
 }
 
 /// ScriptManager owns the Lua state and manages script execution
 pub struct ScriptManager {
-    app: Application,
+    backend: Rc<dyn Backend>,
     lua: Rc<Lua>,
-    widgets: Vec<Rc<RefCell<LuaWidget>>>,
+    permissions: Rc<FsPermissions>,
+    positions: Rc<PositionStore>,
+    widgets: RefCell<Vec<Rc<RefCell<LuaWidget>>>>,
+    scripts_watch: RefCell<Option<Box<dyn Watch>>>,
 }
 
 impl ScriptManager {
-    /// Create a new ScriptManager
-    pub fn new(app: &Application) -> Self {
+    /// Create a new ScriptManager showing widgets through GTK
+    pub fn new(app: &Application, config: &ScriptsConfig) -> Self {
+        Self::with_backend(
+            Rc::new(GtkBackend::new(app)),
+            FsPermissions::new(&config.fs_allow),
+            PositionStore::new(config.positions.clone(), true),
+        )
+    }
+
+    /// Create a new ScriptManager on top of an arbitrary backend
+    pub fn with_backend(
+        backend: Rc<dyn Backend>,
+        permissions: FsPermissions,
+        positions: PositionStore,
+    ) -> Self {
+        let lua = Lua::new();
+        if let Err(e) = tasks::register_await(&lua) {
+            error!("Failed to define await: {}", e);
+        }
+
         ScriptManager {
-            app: app.clone(),
-            lua: Rc::new(Lua::new()),
-            widgets: Vec::new(),
+            backend,
+            lua: Rc::new(lua),
+            permissions: Rc::new(permissions),
+            positions: Rc::new(positions),
+            widgets: RefCell::new(Vec::new()),
+            scripts_watch: RefCell::new(None),
         }
     }
 
-    /// Load and execute a Lua script from the given path
-    pub fn load_script(&mut self, script_path: &Path) -> Result<(), mlua::Error> {
+    /// The Lua state shared by all scripts
+    pub fn lua(&self) -> &Rc<Lua> {
+        &self.lua
+    }
+
+    /// Load and execute a Lua script from the given path, replacing any
+    /// previously loaded instance of it
+    pub fn load_script(&self, script_path: &Path) -> Result<(), mlua::Error> {
         info!("Loading script: {:?}", script_path);
+        self.unload_script(script_path);
 
         let lua = self.lua.clone();
-        // let globals = lua.globals();
 
         // Create widget state
         let widget = Rc::new(RefCell::new(LuaWidget {
-            window: None,
+            path: script_path.to_path_buf(),
+            windows: Vec::new(),
+            watches: Vec::new(),
             update_interval: 60, // Default update interval in seconds
+            running: true,
         }));
-        self.widgets.push(widget.clone());
+        self.widgets.borrow_mut().push(widget.clone());
+
+        let ctx = ScriptContext {
+            lua: Rc::downgrade(&self.lua),
+            backend: self.backend.clone(),
+            permissions: self.permissions.clone(),
+            positions: self.positions.clone(),
+            widget,
+        };
+
+        // Each script gets its own environment, falling back to the globals,
+        // so callbacks stay bound to the script that registered them
+        let env = lua.create_table()?;
+        let meta = lua.create_table()?;
+        meta.set("__index", lua.globals())?;
+        env.set_metatable(Some(meta));
 
         // Register GTK API functions
-        self.register_gtk_api(&lua, widget.clone())?;
+        self.register_gtk_api(&lua, &env, &ctx)?;
+
+        // Register timer functions
+        self.register_timer_api(&lua, &env, &ctx)?;
 
         // Register HTTP functions
-        self.register_http_api(&lua)?;
+        self.register_http_api(&lua, &env, &ctx)?;
 
         // Register helper functions
-        self.register_helper_functions(&lua)?;
+        self.register_helper_functions(&lua, &env, &ctx)?;
+
+        // Register async/await support
+        tasks::register_async_api(&lua, &env, &ctx)?;
+
+        // Register file system functions
+        fs::register_fs_api(&lua, &env, &ctx)?;
+
+        // Register D-Bus functions
+        dbus::register_dbus_api(&lua, &env, &ctx)?;
+
+        // Register Sway IPC functions
+        sway::register_sway_api(&lua, &env, &ctx)?;
 
         // Execute the script
         let script_content = std::fs::read_to_string(script_path)?;
-        lua.load(&script_content).exec()?;
+        lua.load(&script_content)
+            .set_name(script_path.to_string_lossy())?
+            .set_environment(env)?
+            .exec()?;
 
         Ok(())
     }
 
+    /// Stop a script: pending callbacks and tasks are cancelled and its
+    /// windows closed. Returns false if the script was not loaded.
+    pub fn unload_script(&self, script_path: &Path) -> bool {
+        let (unloaded, kept): (Vec<_>, Vec<_>) = self
+            .widgets
+            .take()
+            .into_iter()
+            .partition(|widget| widget.borrow().path == script_path);
+        *self.widgets.borrow_mut() = kept;
+
+        for widget in &unloaded {
+            info!("Unloading script: {:?}", script_path);
+            let mut widget = widget.borrow_mut();
+            widget.running = false;
+            widget.watches.clear();
+            for window in widget.windows.drain(..) {
+                window.close();
+            }
+        }
+
+        !unloaded.is_empty()
+    }
+
+    /// Reload scripts when they change on disk and unload them when removed
+    pub fn watch_scripts(self: &Rc<Self>) {
+        let manager = Rc::downgrade(self);
+        let watch = self.backend.watch(
+            &scripts_dir(),
+            Box::new(move |event, path| {
+                let Some(manager) = manager.upgrade() else {
+                    return;
+                };
+                if path.extension().is_none_or(|ext| ext != "lua") {
+                    return;
+                }
+                match event {
+                    "created" | "done" => {
+                        if let Err(e) = manager.load_script(path) {
+                            error!("Failed to reload script {:?}: {}", path, e);
+                        }
+                    }
+                    "deleted" => {
+                        manager.unload_script(path);
+                    }
+                    _ => {}
+                }
+            }),
+        );
+        match watch {
+            Ok(watch) => *self.scripts_watch.borrow_mut() = Some(watch),
+            Err(e) => error!("Failed to watch scripts directory: {}", e),
+        }
+    }
+
     /// Register GTK API functions with Lua
     fn register_gtk_api(
         &self,
         lua: &Lua,
-        widget: Rc<RefCell<LuaWidget>>,
+        env: &Table,
+        ctx: &ScriptContext,
     ) -> Result<(), mlua::Error> {
-        let globals = lua.globals();
-        let app = self.app.clone();
-
-        {
-            let create_window =
-                lua.create_function(move |lua, (title, width, height): (String, i32, i32)| {
-                    let app_clone = app.clone();
-                    let widget_clone = widget.clone();
-
-                    info!("Creating window: {}", title);
-                    let window = ApplicationWindow::builder()
-                        .application(&app_clone)
-                        .title(&title)
-                        .default_width(width)
-                        .default_height(height)
-                        .build();
-
-                    window.init_layer_shell();
-                    window.set_layer(Layer::Background);
-                    window.set_anchor(Edge::Top, true);
-                    window.set_anchor(Edge::Left, true);
-
-                    // Make window transparent
-                    window.set_app_paintable(true);
-                    window.connect_draw(|_, cr| {
-                        cr.set_source_rgba(0.0, 0.0, 0.0, 0.0);
-                        cr.paint().unwrap();
-                        false.into()
-                    });
-
-                    widget_clone.borrow_mut().window = Some(window.clone());
-
-                    // Create a table to hold window methods
-                    let window_table = lua.create_table()?;
-
-                    // set_margin method
-                    {
-                        let window_clone = window.clone();
-                        let set_margin = lua.create_function(
-                            move |_, (_this, edge, margin): (mlua::Table, String, i32)| {
-                                debug!("Setting margin: {} {:#?}", edge, margin);
-                                let edge = match edge.as_str() {
-                                    "top" => Edge::Top,
-                                    "bottom" => Edge::Bottom,
-                                    "left" => Edge::Left,
-                                    "right" => Edge::Right,
-                                    _ => {
-                                        return Err(mlua::Error::RuntimeError(
-                                            "Invalid edge".to_string(),
-                                        ));
-                                    }
-                                };
-                                window_clone.set_layer_shell_margin(edge, margin);
-                                Ok(())
-                            },
-                        )?;
-                        window_table.set("set_margin", set_margin)?;
+        let ctx = ctx.clone();
+
+        // create_window(title, width, height, {output = "DP-1", name = "clock"})
+        // shows the window on one output, or on every output with output =
+        // "all". The window is shown and hidden by name, its title by default.
+        let create_window = lua.create_function(
+            move |lua, (title, width, height, options): (String, i32, i32, Option<Table>)| {
+                let (output, name) = match options {
+                    Some(options) => (
+                        options.get::<_, Option<String>>("output")?,
+                        options.get::<_, Option<String>>("name")?,
+                    ),
+                    None => (None, None),
+                };
+                let window: Rc<dyn WindowHandle> = match output {
+                    Some(output) => {
+                        OutputWindow::new(ctx.backend.clone(), &title, width, height, &output)
                     }
+                    None => ctx.backend.create_window(&title, width, height, None),
+                };
+                ctx.widget.borrow_mut().windows.push(window.clone());
+
+                let window = Rc::new(ScriptWindow {
+                    window,
+                    name: name.unwrap_or_else(|| title.clone()),
+                    shown: Cell::new(false),
+                });
+                let registration = visibility::register(&window.name, window.clone());
+                ctx.keep_watch(Box::new(registration));
+                window_table(lua, window, &ctx, &title)
+            },
+        )?;
+        env.set("create_window", create_window)?;
+
+        // widgets.show/hide/toggle(name) control any widget by name, such as
+        // "calendar" or another script's window
+        let widgets = lua.create_table()?;
+        widgets.set(
+            "show",
+            lua.create_function(|_, name: String| {
+                visibility::set_visible(&name, true).map_err(mlua::Error::RuntimeError)
+            })?,
+        )?;
+        widgets.set(
+            "hide",
+            lua.create_function(|_, name: String| {
+                visibility::set_visible(&name, false).map_err(mlua::Error::RuntimeError)
+            })?,
+        )?;
+        widgets.set(
+            "toggle",
+            lua.create_function(|_, name: String| {
+                visibility::toggle(&name).map_err(mlua::Error::RuntimeError)
+            })?,
+        )?;
+        widgets.set(
+            "is_visible",
+            lua.create_function(|_, name: String| Ok(visibility::is_visible(&name)))?,
+        )?;
+        widgets.set(
+            "list",
+            lua.create_function(|_, ()| Ok(visibility::names()))?,
+        )?;
+        env.set("widgets", widgets)?;
 
-                    // show method
-                    {
-                        let window_clone = window.clone();
-                        let show = lua.create_function(move |_, ()| {
-                            window_clone.show_all();
-                            Ok(())
-                        })?;
-                        window_table.set("show", show)?;
-                    }
+        Ok(())
+    }
 
-                    // set_update_interval method
-                    {
-                        let widget_clone = widget_clone.clone();
-                        let set_update_interval =
-                            lua.create_function(move |_, (_this, interval): (mlua::Table, u64)| {
-                                widget_clone.borrow_mut().update_interval = interval;
-                                Ok(())
-                            })?;
-                        window_table.set("set_update_interval", set_update_interval)?;
-                    }
+    /// Register timer functions with Lua
+    fn register_timer_api(
+        &self,
+        lua: &Lua,
+        env: &Table,
+        ctx: &ScriptContext,
+    ) -> Result<(), mlua::Error> {
+        // set_timeout(ms, fn) runs fn once after ms milliseconds
+        {
+            let ctx = ctx.clone();
+            let set_timeout = lua.create_function(move |lua, (ms, func): (u64, Function)| {
+                let mut callback = lua_callback(lua, &ctx, func)?;
+                ctx.backend.add_timeout(
+                    Duration::from_millis(ms),
+                    Box::new(move || {
+                        callback();
+                        false
+                    }),
+                );
+                Ok(())
+            })?;
+            env.set("set_timeout", set_timeout)?;
+        }
 
-                    // add_box method
-                    {
-                        let window_clone = window.clone();
-                        let add_box = lua.create_function(
-                            move |lua, (_this, orientation, spacing): (mlua::Table, String, i32)| {
-                                let orientation = match orientation.as_str() {
-                                    "vertical" => Orientation::Vertical,
-                                    "horizontal" => Orientation::Horizontal,
-                                    _ => {
-                                        return Err(mlua::Error::RuntimeError(
-                                            "Invalid orientation".to_string(),
-                                        ));
-                                    }
-                                };
-
-                                let container = GtkBox::new(orientation, spacing);
-                                window_clone.add(&container);
-
-                                // Create a table to hold box methods
-                                let box_table = lua.create_table()?;
-
-                                // add_label method
-                                {
-                                    let container_clone = container.clone();
-                                    let add_label = lua.create_function(
-                                        move |lua,
-                                              (_this, text, font_size): (
-                                            mlua::Table,
-                                            String,
-                                            i32,
-                                        )| {
-                                            let label = Label::new(Some(&text));
-
-                                            // Apply CSS for font size
-                                            let css_provider = gtk::CssProvider::new();
-                                            let css = format!(
-                                                "label {{ font-size: {}px; color: white; }}",
-                                                font_size
-                                            );
-                                            css_provider.load_from_data(css.as_bytes()).unwrap();
-
-                                            let style_context = label.style_context();
-                                            style_context.add_provider(
-                                                &css_provider,
-                                                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-                                            );
-
-                                            container_clone.pack_start(&label, true, true, 0);
-
-                                            // Create a table to hold label methods
-                                            let label_table = lua.create_table()?;
-
-                                            // set_text method
-                                            {
-                                                let label_clone = label.clone();
-                                                let set_text =
-                                                    lua.create_function(move |_, (_this, text): (mlua::Table, String)| {
-                                                        label_clone.set_text(&text);
-                                                        Ok(())
-                                                    })?;
-                                                label_table.set("set_text", set_text)?;
-                                            }
-
-                                            // set_css method for custom styling
-                                            {
-                                                let label_clone = label.clone();
-                                                let set_css = lua.create_function(
-                                                    move |_, (_this, css): (mlua::Table, String)| {
-                                                        let css_provider = gtk::CssProvider::new();
-                                                        css_provider
-                                                            .load_from_data(css.as_bytes())
-                                                            .unwrap();
-
-                                                        let style_context =
-                                                            label_clone.style_context();
-                                                        style_context.add_provider(
-                                                &css_provider,
-                                                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-                                            );
-                                                        Ok(())
-                                                    },
-                                                )?;
-                                                label_table.set("set_css", set_css)?;
-                                            }
-
-                                            Ok(label_table)
-                                        },
-                                    )?;
-                                    box_table.set("add_label", add_label)?;
-                                }
-
-                                // set_css method for the box
-                                {
-                                    let container_clone = container.clone();
-                                    let set_css = lua.create_function(move |_, (_this, css): (mlua::Table, String)| {
-                                        let css_provider = gtk::CssProvider::new();
-                                        css_provider.load_from_data(css.as_bytes()).unwrap();
-
-                                        let style_context = container_clone.style_context();
-                                        style_context.add_provider(
-                                            &css_provider,
-                                            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-                                        );
-                                        Ok(())
-                                    })?;
-                                    box_table.set("set_css", set_css)?;
-                                }
-
-                                Ok(box_table)
-                            },
-                        )?;
-                        window_table.set("add_box", add_box)?;
-                    }
+        // set_interval(ms, fn) runs fn every ms milliseconds until it returns false
+        {
+            let ctx = ctx.clone();
+            let set_interval = lua.create_function(move |lua, (ms, func): (u64, Function)| {
+                let callback = lua_callback(lua, &ctx, func)?;
+                ctx.backend.add_timeout(Duration::from_millis(ms), callback);
+                Ok(())
+            })?;
+            env.set("set_interval", set_interval)?;
+        }
 
-                    Ok(window_table)
-                })?;
-            globals.set("create_window", create_window)?;
+        // schedule_update(fn) runs fn every update interval of the script's window
+        {
+            let ctx = ctx.clone();
+            let schedule_update = lua.create_function(move |lua, func: Function| {
+                let interval = ctx.widget.borrow().update_interval;
+                let callback = lua_callback(lua, &ctx, func)?;
+                ctx.backend
+                    .add_timeout(Duration::from_secs(interval), callback);
+                Ok(())
+            })?;
+            env.set("schedule_update", schedule_update)?;
+        }
+
+        // sleep(ms) returns an operation that completes after ms milliseconds
+        {
+            let ctx = ctx.clone();
+            let sleep = lua.create_function(move |_, ms: u64| {
+                let op = Pending::default();
+                let mut done =
+                    Some(op.resolver(&ctx, |lua, ()| lua.create_registry_value(Value::Nil)));
+                ctx.backend.add_timeout(
+                    Duration::from_millis(ms),
+                    Box::new(move || {
+                        if let Some(done) = done.take() {
+                            done(Ok(()));
+                        }
+                        false
+                    }),
+                );
+                Ok(op)
+            })?;
+            env.set("sleep", sleep)?;
         }
 
         Ok(())
     }
 
     /// Register HTTP API functions with Lua
-    fn register_http_api(&self, lua: &Lua) -> Result<(), mlua::Error> {
-        let globals = lua.globals();
-
-        let fetch_json = lua.create_function(|lua_ctx, url: String| {
-            info!("Fetching JSON from: {}", url);
-            match reqwest::blocking::get(&url) {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        match response.json::<JsonValue>() {
-                            Ok(json) => {
-                                let lua_value =
-                                    ScriptManager::serde_json_to_lua_value(lua_ctx, json);
-                                Ok(lua_value)
-                            }
-                            Err(err) => Err(mlua::Error::RuntimeError(format!(
-                                "Failed to parse JSON: {}",
-                                err
-                            ))),
-                        }
-                    } else {
-                        Err(mlua::Error::RuntimeError(format!(
+    fn register_http_api(
+        &self,
+        lua: &Lua,
+        env: &Table,
+        ctx: &ScriptContext,
+    ) -> Result<(), mlua::Error> {
+        // fetch_json(url) blocks until the decoded JSON body is available
+        {
+            let backend = ctx.backend.clone();
+            let fetch_json = lua.create_function(move |lua_ctx, url: String| {
+                info!("Fetching JSON from: {}", url);
+                let body = backend.http_get(&url).map_err(mlua::Error::RuntimeError)?;
+                match serde_json::from_str::<JsonValue>(&body) {
+                    Ok(json) => Ok(ScriptManager::serde_json_to_lua_value(lua_ctx, json)),
+                    Err(err) => Err(mlua::Error::RuntimeError(format!(
+                        "Failed to parse JSON: {}",
+                        err
+                    ))),
+                }
+            })?;
+            env.set("fetch_json", fetch_json)?;
+        }
+
+        let http = lua.create_table()?;
+
+        // http.get(url) returns an operation resolving to { status, body }
+        {
+            let ctx = ctx.clone();
+            let get = lua.create_function(move |_, url: String| {
+                let op = Pending::default();
+                let done = op.resolver(&ctx, |lua, response: HttpResponse| {
+                    let result = lua.create_table()?;
+                    result.set("status", response.status)?;
+                    result.set("body", response.body)?;
+                    lua.create_registry_value(result)
+                });
+                ctx.backend.http_get_async(&url, done);
+                Ok(op)
+            })?;
+            http.set("get", get)?;
+        }
+
+        // http.json(url) returns an operation resolving to the decoded JSON body
+        {
+            let ctx = ctx.clone();
+            let json = lua.create_function(move |_, url: String| {
+                let op = Pending::default();
+                let done = op.resolver(&ctx, |lua, response: HttpResponse| {
+                    if !(200..300).contains(&response.status) {
+                        return Err(mlua::Error::RuntimeError(format!(
                             "HTTP error: {}",
-                            response.status()
-                        )))
+                            response.status
+                        )));
                     }
-                }
-                Err(err) => Err(mlua::Error::RuntimeError(format!(
-                    "Failed to fetch URL: {}",
-                    err
-                ))),
-            }
-        })?;
-        globals.set("fetch_json", fetch_json)?;
+                    let json =
+                        serde_json::from_str::<JsonValue>(&response.body).map_err(|err| {
+                            mlua::Error::RuntimeError(format!("Failed to parse JSON: {}", err))
+                        })?;
+                    lua.create_registry_value(ScriptManager::serde_json_to_lua_value(lua, json))
+                });
+                ctx.backend.http_get_async(&url, done);
+                Ok(op)
+            })?;
+            http.set("json", json)?;
+        }
+
+        env.set("http", http)?;
 
         Ok(())
     }
 
     /// Register helper functions with Lua
-    fn register_helper_functions(&self, lua: &Lua) -> Result<(), mlua::Error> {
-        let globals = lua.globals();
-
+    fn register_helper_functions(
+        &self,
+        lua: &Lua,
+        env: &Table,
+        ctx: &ScriptContext,
+    ) -> Result<(), mlua::Error> {
         // Print function for debugging
         let print = lua.create_function(|_, message: String| {
             info!("[Lua] {}", message);
             Ok(())
         })?;
-        globals.set("log", print)?;
+        env.set("log", print)?;
+
+        // Run a shell command and return its status and output
+        {
+            let backend = ctx.backend.clone();
+            let exec = lua.create_function(move |lua, command: String| {
+                let output = backend.exec(&command).map_err(mlua::Error::RuntimeError)?;
+                exec_table(lua, output)
+            })?;
+            env.set("exec", exec)?;
+        }
+
+        // exec_async(command) returns an operation resolving to the same table
+        {
+            let ctx = ctx.clone();
+            let exec_async = lua.create_function(move |_, command: String| {
+                let op = Pending::default();
+                let done = op.resolver(&ctx, |lua, output: ExecOutput| {
+                    lua.create_registry_value(exec_table(lua, output)?)
+                });
+                ctx.backend.exec_async(&command, done);
+                Ok(op)
+            })?;
+            env.set("exec_async", exec_async)?;
+        }
 
         Ok(())
     }
 
     /// Convert a serde_json::Value to an mlua::Value
-    fn serde_json_to_lua_value(lua: &Lua, json: JsonValue) -> Value {
+    pub(crate) fn serde_json_to_lua_value(lua: &Lua, json: JsonValue) -> Value<'_> {
         match json {
             JsonValue::Null => Value::Nil,
             JsonValue::Bool(b) => Value::Boolean(b),
@@ -360,16 +530,8 @@ impl ScriptManager {
     }
 
     /// Load all scripts from the scripts directory
-    pub fn load_scripts(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // Get the XDG config directory for our app
-        let scripts_dir = if let Some(config_dir) = dirs::config_dir() {
-            let mut path = config_dir;
-            path.push("swaydgets");
-            path.push("scripts");
-            path
-        } else {
-            PathBuf::from("./scripts")
-        };
+    pub fn load_scripts(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let scripts_dir = scripts_dir();
 
         // Create directory if it doesn't exist
         if !scripts_dir.exists() {
@@ -389,13 +551,381 @@ impl ScriptManager {
             let entry = entry?;
             let path = entry.path();
 
-            if path.extension().map_or(false, |ext| ext == "lua") {
-                if let Err(e) = self.load_script(&path) {
-                    error!("Failed to load script {:?}: {}", path, e);
-                }
+            if path.extension().is_some_and(|ext| ext == "lua")
+                && let Err(e) = self.load_script(&path)
+            {
+                error!("Failed to load script {:?}: {}", path, e);
             }
         }
 
         Ok(())
     }
 }
+
+/// The directory user scripts are loaded from
+pub fn scripts_dir() -> PathBuf {
+    // Get the XDG config directory for our app
+    if let Some(config_dir) = dirs::config_dir() {
+        let mut path = config_dir;
+        path.push("swaydgets");
+        path.push("scripts");
+        path
+    } else {
+        PathBuf::from("./scripts")
+    }
+}
+
+/// Wrap a Lua function into a timer callback.
+///
+/// The callback keeps running until the function returns `false` or raises
+/// an error, which is reported through the backend, or until the script is
+/// unloaded.
+fn lua_callback(
+    lua: &Lua,
+    ctx: &ScriptContext,
+    func: Function,
+) -> Result<Box<dyn FnMut() -> bool>, mlua::Error> {
+    let key = lua.create_registry_value(func)?;
+    let ctx = ctx.clone();
+    Ok(Box::new(move || {
+        let Some(lua) = ctx.lua() else {
+            return false;
+        };
+        let result = lua
+            .registry_value::<Function>(&key)
+            .and_then(|func| func.call::<_, Value>(()));
+        match result {
+            Ok(Value::Boolean(false)) => false,
+            Ok(_) => true,
+            Err(e) => {
+                ctx.backend.script_error(e.to_string());
+                false
+            }
+        }
+    }))
+}
+
+/// Build the result table of `exec` and `exec_async`
+fn exec_table(lua: &Lua, output: ExecOutput) -> Result<Table<'_>, mlua::Error> {
+    let result = lua.create_table()?;
+    result.set("status", output.status)?;
+    result.set("stdout", output.stdout)?;
+    result.set("stderr", output.stderr)?;
+    Ok(result)
+}
+
+// A script window, shown and hidden by name once the script has shown it
+struct ScriptWindow {
+    window: Rc<dyn WindowHandle>,
+    name: String,
+    shown: Cell<bool>,
+}
+
+impl Toggle for ScriptWindow {
+    fn set_visible(&self, visible: bool) {
+        if !self.shown.get() {
+            return;
+        }
+        if visible {
+            self.window.show();
+        } else {
+            self.window.hide();
+        }
+    }
+}
+
+/// Build the Lua table exposing a window's methods
+fn window_table<'lua>(
+    lua: &'lua Lua,
+    script_window: Rc<ScriptWindow>,
+    ctx: &ScriptContext,
+    title: &str,
+) -> Result<Table<'lua>, mlua::Error> {
+    let window_table = lua.create_table()?;
+    let window = script_window.window.clone();
+    let widget = ctx.widget.clone();
+
+    // set_margin method
+    {
+        let window = window.clone();
+        let set_margin =
+            lua.create_function(move |_, (_this, edge, margin): (Table, String, i32)| {
+                window.set_margin(parse_edge(&edge)?, margin);
+                Ok(())
+            })?;
+        window_table.set("set_margin", set_margin)?;
+    }
+
+    // set_layer method: background, bottom, top or overlay
+    {
+        let window = window.clone();
+        let set_layer = lua.create_function(move |_, (_this, layer): (Table, String)| {
+            let layer = match layer.as_str() {
+                "background" => Layer::Background,
+                "bottom" => Layer::Bottom,
+                "top" => Layer::Top,
+                "overlay" => Layer::Overlay,
+                _ => {
+                    return Err(mlua::Error::RuntimeError(format!(
+                        "Invalid layer: {}",
+                        layer
+                    )));
+                }
+            };
+            window.set_layer(layer);
+            Ok(())
+        })?;
+        window_table.set("set_layer", set_layer)?;
+    }
+
+    // set_anchor method: {top = true, left = true} anchors to those edges
+    // and releases the others
+    {
+        let window = window.clone();
+        let set_anchor = lua.create_function(move |_, (_this, anchors): (Table, Table)| {
+            for pair in anchors.clone().pairs::<String, Value>() {
+                let (edge, _) = pair?;
+                parse_edge(&edge)?;
+            }
+            for (name, edge) in EDGES {
+                let anchored = anchors.get::<_, Option<bool>>(name)?.unwrap_or(false);
+                window.set_anchor(edge, anchored);
+            }
+            Ok(())
+        })?;
+        window_table.set("set_anchor", set_anchor)?;
+    }
+
+    // set_position method: "center", an edge such as "top" or a corner such
+    // as "bottom-right", replacing the current anchors
+    {
+        let window = window.clone();
+        let set_position = lua.create_function(move |_, (_this, position): (Table, String)| {
+            let anchored: Vec<Edge> = match position.as_str() {
+                "center" => Vec::new(),
+                position => position
+                    .split('-')
+                    .map(parse_edge)
+                    .collect::<Result<_, _>>()?,
+            };
+            for (_, edge) in EDGES {
+                window.set_anchor(edge, anchored.contains(&edge));
+            }
+            Ok(())
+        })?;
+        window_table.set("set_position", set_position)?;
+    }
+
+    // set_exclusive_zone method: space reserved at the anchored edge, or -1
+    // to ignore other surfaces' zones
+    {
+        let window = window.clone();
+        let set_exclusive_zone = lua.create_function(move |_, (_this, zone): (Table, i32)| {
+            window.set_exclusive_zone(zone);
+            Ok(())
+        })?;
+        window_table.set("set_exclusive_zone", set_exclusive_zone)?;
+    }
+
+    // set_namespace method: the layer namespace sway rules such as
+    // layer_effects match on; call before show
+    {
+        let window = window.clone();
+        let set_namespace =
+            lua.create_function(move |_, (_this, namespace): (Table, String)| {
+                window.set_namespace(&namespace);
+                Ok(())
+            })?;
+        window_table.set("set_namespace", set_namespace)?;
+    }
+
+    // set_click_through method: pointer input passes through the window to
+    // whatever is below, except over interactive boxes
+    {
+        let window = window.clone();
+        let set_click_through =
+            lua.create_function(move |_, (_this, enabled): (Table, bool)| {
+                window.set_click_through(enabled);
+                Ok(())
+            })?;
+        window_table.set("set_click_through", set_click_through)?;
+    }
+
+    // set_draggable method: the window can be moved with the mouse in
+    // edit-layout mode, and keeps the position it was dragged to
+    {
+        let window = window.clone();
+        let positions = ctx.positions.clone();
+        let file_name = widget
+            .borrow()
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let key = format!("{}/{}", file_name, title);
+        let set_draggable = lua.create_function(move |_, _this: Table| {
+            let saved = positions.get(&key).map(|position| (position.x, position.y));
+            let positions = positions.clone();
+            let key = key.clone();
+            window.make_draggable(
+                saved,
+                Box::new(move |x, y| positions.set(&key, Position { x, y })),
+            );
+            Ok(())
+        })?;
+        window_table.set("set_draggable", set_draggable)?;
+    }
+
+    // set_transition method: "none", "fade" or "slide", with the duration
+    // in milliseconds
+    {
+        let window = window.clone();
+        let set_transition = lua.create_function(
+            move |_, (_this, kind, duration): (Table, String, Option<u64>)| {
+                let transition = Transition::parse(&kind, duration.unwrap_or(200))
+                    .map_err(mlua::Error::RuntimeError)?;
+                window.set_transition(transition);
+                Ok(())
+            },
+        )?;
+        window_table.set("set_transition", set_transition)?;
+    }
+
+    // show method: the window appears unless its name has been hidden
+    {
+        let script_window = script_window.clone();
+        let show = lua.create_function(move |_, ()| {
+            script_window.shown.set(true);
+            if visibility::is_visible(&script_window.name) {
+                script_window.window.show();
+            }
+            Ok(())
+        })?;
+        window_table.set("show", show)?;
+    }
+
+    // hide and toggle methods, which apply to every window sharing its name
+    {
+        let name = script_window.name.clone();
+        let hide = lua.create_function(move |_, _this: Table| {
+            visibility::set_visible(&name, false).map_err(mlua::Error::RuntimeError)
+        })?;
+        window_table.set("hide", hide)?;
+
+        let name = script_window.name.clone();
+        let toggle = lua.create_function(move |_, _this: Table| {
+            visibility::toggle(&name).map_err(mlua::Error::RuntimeError)
+        })?;
+        window_table.set("toggle", toggle)?;
+    }
+
+    // set_update_interval method
+    {
+        let set_update_interval =
+            lua.create_function(move |_, (_this, interval): (Table, u64)| {
+                widget.borrow_mut().update_interval = interval;
+                Ok(())
+            })?;
+        window_table.set("set_update_interval", set_update_interval)?;
+    }
+
+    // add_box method
+    {
+        let add_box = lua.create_function(
+            move |lua, (_this, orientation, spacing): (Table, String, i32)| {
+                let orientation = match orientation.as_str() {
+                    "vertical" => Orientation::Vertical,
+                    "horizontal" => Orientation::Horizontal,
+                    _ => {
+                        return Err(mlua::Error::RuntimeError("Invalid orientation".to_string()));
+                    }
+                };
+                box_table(lua, window.add_box(orientation, spacing))
+            },
+        )?;
+        window_table.set("add_box", add_box)?;
+    }
+
+    Ok(window_table)
+}
+
+// Layer shell edges by name
+const EDGES: [(&str, Edge); 4] = [
+    ("top", Edge::Top),
+    ("bottom", Edge::Bottom),
+    ("left", Edge::Left),
+    ("right", Edge::Right),
+];
+
+// Parse an edge name used by the window methods
+fn parse_edge(name: &str) -> Result<Edge, mlua::Error> {
+    EDGES
+        .iter()
+        .find(|(edge_name, _)| *edge_name == name)
+        .map(|(_, edge)| *edge)
+        .ok_or_else(|| mlua::Error::RuntimeError(format!("Invalid edge: {}", name)))
+}
+
+/// Build the Lua table exposing a box's methods
+fn box_table(lua: &Lua, container: Rc<dyn BoxHandle>) -> Result<Table<'_>, mlua::Error> {
+    let box_table = lua.create_table()?;
+
+    // add_label method
+    {
+        let container = container.clone();
+        let add_label =
+            lua.create_function(move |lua, (_this, text, font_size): (Table, String, i32)| {
+                label_table(lua, container.add_label(&text, font_size))
+            })?;
+        box_table.set("add_label", add_label)?;
+    }
+
+    // set_css method for the box
+    {
+        let container = container.clone();
+        let set_css = lua.create_function(move |_, (_this, css): (Table, String)| {
+            container.set_css(&css);
+            Ok(())
+        })?;
+        box_table.set("set_css", set_css)?;
+    }
+
+    // set_interactive method: the box keeps receiving clicks while its
+    // window is click-through
+    {
+        let set_interactive =
+            lua.create_function(move |_, (_this, interactive): (Table, bool)| {
+                container.set_interactive(interactive);
+                Ok(())
+            })?;
+        box_table.set("set_interactive", set_interactive)?;
+    }
+
+    Ok(box_table)
+}
+
+/// Build the Lua table exposing a label's methods
+fn label_table(lua: &Lua, label: Rc<dyn LabelHandle>) -> Result<Table<'_>, mlua::Error> {
+    let label_table = lua.create_table()?;
+
+    // set_text method
+    {
+        let label = label.clone();
+        let set_text = lua.create_function(move |_, (_this, text): (Table, String)| {
+            label.set_text(&text);
+            Ok(())
+        })?;
+        label_table.set("set_text", set_text)?;
+    }
+
+    // set_css method for custom styling
+    {
+        let set_css = lua.create_function(move |_, (_this, css): (Table, String)| {
+            label.set_css(&css);
+            Ok(())
+        })?;
+        label_table.set("set_css", set_css)?;
+    }
+
+    Ok(label_table)
+}
@@ -0,0 +1,268 @@
+use gtk::glib;
+use log::{info, warn};
+use mlua::{Function, Lua, Table, Value};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use swayipc::{Connection, Event, EventType, Fallible, Node, Output, Workspace};
+
+use crate::backend::Watch;
+use crate::script::{ScriptContext, ScriptManager};
+
+// Events forwarded to subscribers
+const EVENTS: [EventType; 4] = [
+    EventType::Window,
+    EventType::Workspace,
+    EventType::Output,
+    EventType::Mode,
+];
+
+// Delays between attempts to reach Sway after losing it
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// What subscribers hear from Sway
+#[derive(Debug)]
+pub enum SwayEvent {
+    /// The event stream is up, after startup or a Sway restart
+    Connected,
+    /// The event stream went away; reconnection is retried in the background
+    Disconnected,
+    Event(Event),
+}
+
+type Subscriber = Rc<dyn Fn(&SwayEvent)>;
+
+thread_local! {
+    // One connection for commands and queries, opened on first use and
+    // replaced when it stops working
+    static COMMANDS: RefCell<Option<Connection>> = const { RefCell::new(None) };
+
+    static SUBSCRIBERS: RefCell<Vec<(u64, Subscriber)>> = const { RefCell::new(Vec::new()) };
+    static NEXT_SUBSCRIBER: Cell<u64> = const { Cell::new(0) };
+    static LISTENING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keeps a subscription to Sway events until dropped
+pub struct Subscription(u64);
+
+impl Watch for Subscription {}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let _ = SUBSCRIBERS
+            .try_with(|subscribers| subscribers.borrow_mut().retain(|(id, _)| *id != self.0));
+    }
+}
+
+/// Call `callback` on the GTK loop for every Sway event. The event stream is
+/// shared by all subscribers and started on first use.
+pub fn subscribe(callback: impl Fn(&SwayEvent) + 'static) -> Subscription {
+    listen();
+    let id = NEXT_SUBSCRIBER.with(|next| next.replace(next.get() + 1));
+    SUBSCRIBERS.with(|subscribers| subscribers.borrow_mut().push((id, Rc::new(callback))));
+    Subscription(id)
+}
+
+/// Run a Sway command, failing if Sway is unreachable or rejects it
+pub fn run_command(command: &str) -> Result<(), String> {
+    let outcomes = request(|connection| connection.run_command(command))?;
+    let errors: Vec<String> = outcomes
+        .into_iter()
+        .filter_map(|outcome| outcome.err().map(|e| e.to_string()))
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Sway rejected {}: {}", command, errors.join(", ")))
+    }
+}
+
+pub fn get_tree() -> Result<Node, String> {
+    request(|connection| connection.get_tree())
+}
+
+pub fn get_outputs() -> Result<Vec<Output>, String> {
+    request(|connection| connection.get_outputs())
+}
+
+pub fn get_workspaces() -> Result<Vec<Workspace>, String> {
+    request(|connection| connection.get_workspaces())
+}
+
+// Send a request on the shared connection. A connection left stale by a Sway
+// restart fails once, so the request is retried on a fresh one.
+fn request<T>(send: impl Fn(&mut Connection) -> Fallible<T>) -> Result<T, String> {
+    COMMANDS.with(|commands| {
+        let mut commands = commands.borrow_mut();
+        let mut reused = commands.is_some();
+        loop {
+            let connection = match commands.as_mut() {
+                Some(connection) => connection,
+                None => commands.insert(
+                    Connection::new().map_err(|e| format!("Failed to connect to Sway: {}", e))?,
+                ),
+            };
+            match send(connection) {
+                Ok(reply) => return Ok(reply),
+                Err(e) => {
+                    *commands = None;
+                    if !reused {
+                        return Err(format!("Sway request failed: {}", e));
+                    }
+                    reused = false;
+                }
+            }
+        }
+    })
+}
+
+// Start the event thread and hand its events to the subscribers
+fn listen() {
+    if LISTENING.with(|listening| listening.replace(true)) {
+        return;
+    }
+
+    let (sender, receiver) = async_channel::unbounded();
+    thread::spawn(move || read_events(sender));
+    glib::MainContext::default().spawn_local(async move {
+        while let Ok(event) = receiver.recv().await {
+            // Subscribers may subscribe or unsubscribe while handling the event
+            let subscribers: Vec<Subscriber> = SUBSCRIBERS.with(|subscribers| {
+                subscribers
+                    .borrow()
+                    .iter()
+                    .map(|(_, callback)| callback.clone())
+                    .collect()
+            });
+            for callback in subscribers {
+                callback(&event);
+            }
+        }
+    });
+}
+
+// Forward Sway events, reconnecting with a growing delay whenever the
+// socket goes away. Stops once the GTK side is gone.
+fn read_events(sender: async_channel::Sender<SwayEvent>) {
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        match Connection::new().and_then(|connection| connection.subscribe(EVENTS)) {
+            Ok(events) => {
+                info!("Subscribed to Sway events");
+                backoff = MIN_BACKOFF;
+                if sender.send_blocking(SwayEvent::Connected).is_err() {
+                    return;
+                }
+                for event in events {
+                    match event {
+                        Ok(event) => {
+                            if sender.send_blocking(SwayEvent::Event(event)).is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Lost the Sway event stream: {}", e);
+                            break;
+                        }
+                    }
+                }
+                if sender.send_blocking(SwayEvent::Disconnected).is_err() {
+                    return;
+                }
+            }
+            Err(e) => warn!("Failed to reach Sway, retrying in {:?}: {}", backoff, e),
+        }
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Register the `sway` table. `sway.command(cmd)` runs a command,
+/// `sway.tree()` and `sway.workspaces()` query Sway, and `sway.on(kind, fn)`
+/// calls `fn(event)` for "window", "workspace", "output" and "mode" events, or
+/// `fn()` on "connected" and "disconnected". Subscriptions end when the
+/// script is unloaded.
+pub fn register_sway_api(lua: &Lua, env: &Table, ctx: &ScriptContext) -> Result<(), mlua::Error> {
+    let sway = lua.create_table()?;
+
+    let command = lua.create_function(|_, command: String| {
+        run_command(&command).map_err(mlua::Error::RuntimeError)
+    })?;
+    sway.set("command", command)?;
+
+    let tree = lua.create_function(|lua, ()| {
+        let tree = get_tree().map_err(mlua::Error::RuntimeError)?;
+        to_lua(lua, &tree)
+    })?;
+    sway.set("tree", tree)?;
+
+    let workspaces = lua.create_function(|lua, ()| {
+        let workspaces = get_workspaces().map_err(mlua::Error::RuntimeError)?;
+        to_lua(lua, &workspaces)
+    })?;
+    sway.set("workspaces", workspaces)?;
+
+    {
+        let ctx = ctx.clone();
+        let on = lua.create_function(move |lua, (kind, func): (String, Function)| {
+            if !matches!(
+                kind.as_str(),
+                "window" | "workspace" | "output" | "mode" | "connected" | "disconnected"
+            ) {
+                return Err(mlua::Error::RuntimeError(format!(
+                    "Unknown Sway event {}",
+                    kind
+                )));
+            }
+
+            let key = lua.create_registry_value(func)?;
+            let callback_ctx = ctx.clone();
+            let subscription = subscribe(move |event| {
+                let Some(lua) = callback_ctx.lua() else {
+                    return;
+                };
+                let result = event_to_lua(&lua, &kind, event).and_then(|args| match args {
+                    Some(args) => lua.registry_value::<Function>(&key)?.call::<_, ()>(args),
+                    None => Ok(()),
+                });
+                if let Err(e) = result {
+                    callback_ctx.backend().script_error(e.to_string());
+                }
+            });
+            ctx.keep_watch(Box::new(subscription));
+            Ok(())
+        })?;
+        sway.set("on", on)?;
+    }
+
+    env.set("sway", sway)?;
+    Ok(())
+}
+
+// The arguments for a Lua subscriber to `kind`, None if the event is another kind
+fn event_to_lua<'lua>(
+    lua: &'lua Lua,
+    kind: &str,
+    event: &SwayEvent,
+) -> Result<Option<Vec<Value<'lua>>>, mlua::Error> {
+    let args = match (kind, event) {
+        ("connected", SwayEvent::Connected) | ("disconnected", SwayEvent::Disconnected) => {
+            Vec::new()
+        }
+        ("window", SwayEvent::Event(Event::Window(event))) => vec![to_lua(lua, event)?],
+        ("workspace", SwayEvent::Event(Event::Workspace(event))) => vec![to_lua(lua, event)?],
+        ("output", SwayEvent::Event(Event::Output(event))) => vec![to_lua(lua, event)?],
+        ("mode", SwayEvent::Event(Event::Mode(event))) => vec![to_lua(lua, event)?],
+        _ => return Ok(None),
+    };
+    Ok(Some(args))
+}
+
+// Convert a Sway reply or event to Lua tables through its JSON form
+fn to_lua<'lua>(lua: &'lua Lua, value: &impl serde::Serialize) -> Result<Value<'lua>, mlua::Error> {
+    let json = serde_json::to_value(value)
+        .map_err(|e| mlua::Error::RuntimeError(format!("Failed to convert Sway reply: {}", e)))?;
+    Ok(ScriptManager::serde_json_to_lua_value(lua, json))
+}
@@ -0,0 +1,223 @@
+use mlua::{
+    Function, Lua, RegistryKey, Table, Thread, ThreadStatus, ToLuaMulti, UserData, UserDataMethods,
+    Value,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::script::ScriptContext;
+
+// `await` has to yield, which Rust callbacks cannot do, so it lives in Lua.
+// The coroutine is resumed with (true, value) or (false, error message).
+const AWAIT: &str = r#"
+function await(op)
+    if not coroutine.isyieldable() then
+        error("await can only be used inside async", 2)
+    end
+    local ok, value = coroutine.yield(op)
+    if not ok then
+        error(value, 2)
+    end
+    return value
+end
+"#;
+
+/// Define the global `await`, shared by every script in a Lua state
+pub fn register_await(lua: &Lua) -> Result<(), mlua::Error> {
+    lua.load(AWAIT).set_name("await")?.exec()
+}
+
+/// Register `async` in a script environment.
+///
+/// `async(fn)` runs `fn` as a coroutine and returns a task that can itself be
+/// awaited. Each `await(op)` suspends the coroutine until the operation
+/// completes, and the coroutine is dropped if its script is unloaded or the
+/// task is cancelled.
+pub fn register_async_api(lua: &Lua, env: &Table, ctx: &ScriptContext) -> Result<(), mlua::Error> {
+    let ctx = ctx.clone();
+    let spawn = lua.create_function(move |lua, func: Function| {
+        let thread = lua.create_thread(func)?;
+        let task = Pending::default();
+        task.0.borrow_mut().coroutine = Some(lua.create_registry_value(thread)?);
+        step(lua, &ctx, task.clone(), ());
+        Ok(task)
+    })?;
+    env.set("async", spawn)?;
+
+    Ok(())
+}
+
+// Resume a task's coroutine until it waits on an operation or finishes
+fn step<'lua>(lua: &'lua Lua, ctx: &ScriptContext, task: Pending, args: impl ToLuaMulti<'lua>) {
+    let resumed = {
+        let state = task.0.borrow();
+        let Some(thread) = &state.coroutine else {
+            return;
+        };
+        lua.registry_value::<Thread>(thread)
+    }
+    .and_then(|coroutine| {
+        let values = coroutine.resume::<_, mlua::MultiValue>(args)?;
+        Ok((coroutine.status(), values.into_iter().next()))
+    });
+
+    match resumed {
+        // Suspended in await: continue once the yielded operation is done.
+        // Only the task holds on to its coroutine, so one waiting on an
+        // operation that never completes is still freed when cancelled.
+        Ok((ThreadStatus::Resumable, yielded)) => {
+            let op = match yielded {
+                Some(Value::UserData(data)) => data.borrow::<Pending>().ok().map(|op| op.clone()),
+                _ => None,
+            };
+            let Some(op) = op else {
+                fail(
+                    ctx,
+                    &task,
+                    "async functions can only yield through await".to_string(),
+                );
+                release(lua, &task);
+                return;
+            };
+
+            let ctx = ctx.clone();
+            op.clone().when_done(Box::new(move || {
+                if task.is_cancelled() {
+                    return;
+                }
+                let Some(lua) = ctx.lua() else {
+                    return;
+                };
+                match op.outcome(&lua) {
+                    Ok(outcome) => step(&lua, &ctx, task, outcome),
+                    Err(e) => {
+                        fail(&ctx, &task, e.to_string());
+                        release(&lua, &task);
+                    }
+                }
+            }));
+        }
+        Ok((_, returned)) => {
+            let returned = returned.unwrap_or(Value::Nil);
+            task.complete(
+                lua.create_registry_value(returned)
+                    .map_err(|e| e.to_string()),
+            );
+            release(lua, &task);
+        }
+        Err(e) => {
+            fail(ctx, &task, e.to_string());
+            release(lua, &task);
+        }
+    }
+}
+
+// Drop a task's coroutine and free the registry slots of it and of any
+// other values that are no longer referenced, such as awaited results
+fn release(lua: &Lua, task: &Pending) {
+    let coroutine = task.0.borrow_mut().coroutine.take();
+    drop(coroutine);
+    lua.expire_registry_values();
+}
+
+// Fail a task, reporting the error if nothing is waiting to handle it
+fn fail(ctx: &ScriptContext, task: &Pending, message: String) {
+    if !task.has_waiters() {
+        ctx.backend()
+            .script_error(format!("async task failed: {}", message));
+    }
+    task.complete(Err(message));
+}
+
+/// An operation handed to Lua that completes later, such as a request, a
+/// sleep or an `async` task. Scripts pass it to `await`.
+#[derive(Clone, Default)]
+pub struct Pending(Rc<RefCell<PendingState>>);
+
+#[derive(Default)]
+struct PendingState {
+    result: Option<Result<RegistryKey, String>>,
+    // The coroutine of an `async` task, until it finishes or is cancelled
+    coroutine: Option<RegistryKey>,
+    waiters: Vec<Box<dyn FnOnce()>>,
+    cancelled: bool,
+}
+
+impl Pending {
+    /// A callback converting a backend result to Lua and completing this
+    /// operation. Results arriving after the script is unloaded are dropped.
+    pub fn resolver<T: 'static>(
+        &self,
+        ctx: &ScriptContext,
+        convert: impl FnOnce(&Lua, T) -> Result<RegistryKey, mlua::Error> + 'static,
+    ) -> Box<dyn FnOnce(Result<T, String>)> {
+        let op = self.clone();
+        let ctx = ctx.clone();
+        Box::new(move |result| {
+            let Some(lua) = ctx.lua() else {
+                return;
+            };
+            op.complete(result.and_then(|value| convert(&lua, value).map_err(|e| e.to_string())));
+        })
+    }
+
+    fn complete(&self, result: Result<RegistryKey, String>) {
+        let waiters = {
+            let mut state = self.0.borrow_mut();
+            if state.result.is_some() {
+                return;
+            }
+            state.result = Some(result);
+            std::mem::take(&mut state.waiters)
+        };
+        for waiter in waiters {
+            waiter();
+        }
+    }
+
+    fn when_done(&self, waiter: Box<dyn FnOnce()>) {
+        if self.0.borrow().result.is_some() {
+            waiter();
+        } else {
+            self.0.borrow_mut().waiters.push(waiter);
+        }
+    }
+
+    fn has_waiters(&self) -> bool {
+        !self.0.borrow().waiters.is_empty()
+    }
+
+    fn cancel(&self) {
+        self.0.borrow_mut().cancelled = true;
+        self.complete(Err("cancelled".to_string()));
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.borrow().cancelled
+    }
+
+    // The values `await` is resumed with
+    fn outcome<'lua>(&self, lua: &'lua Lua) -> Result<(bool, Value<'lua>), mlua::Error> {
+        match &self.0.borrow().result {
+            Some(Ok(key)) => Ok((true, lua.registry_value(key)?)),
+            Some(Err(message)) => Ok((false, Value::String(lua.create_string(message)?))),
+            None => Err(mlua::Error::RuntimeError(
+                "operation is still pending".to_string(),
+            )),
+        }
+    }
+}
+
+impl UserData for Pending {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // op:done() tells whether the operation has completed
+        methods.add_method("done", |_, this, ()| Ok(this.0.borrow().result.is_some()));
+
+        // op:cancel() stops a task; anything awaiting it gets an error
+        methods.add_method("cancel", |lua, this, ()| {
+            this.cancel();
+            release(lua, this);
+            Ok(())
+        });
+    }
+}
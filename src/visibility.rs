@@ -0,0 +1,303 @@
+use gtk::glib;
+use gtk::prelude::*;
+use gtk_layer_shell::{Edge, LayerShell};
+use log::info;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::backend::Watch;
+
+// Time between animation frames
+const FRAME: Duration = Duration::from_millis(16);
+
+/// How a widget appears and disappears, with the duration in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    None,
+    Fade(u64),
+    Slide(u64),
+}
+
+impl Transition {
+    /// Parse a transition name: "none", "fade" or "slide"
+    pub fn parse(kind: &str, duration: u64) -> Result<Transition, String> {
+        match kind {
+            "none" => Ok(Transition::None),
+            "fade" => Ok(Transition::Fade(duration)),
+            "slide" => Ok(Transition::Slide(duration)),
+            other => Err(format!(
+                "Unknown transition {}, expected none, fade or slide",
+                other
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transition::None => "none",
+            Transition::Fade(_) => "fade",
+            Transition::Slide(_) => "slide",
+        }
+    }
+
+    pub fn duration(&self) -> u64 {
+        match *self {
+            Transition::None => 0,
+            Transition::Fade(duration) | Transition::Slide(duration) => duration,
+        }
+    }
+}
+
+/// A widget that can be shown and hidden by name
+pub trait Toggle {
+    fn set_visible(&self, visible: bool);
+}
+
+thread_local! {
+    // Registered widgets; windows on several outputs share a name
+    static WIDGETS: RefCell<Vec<(String, Rc<dyn Toggle>)>> = const { RefCell::new(Vec::new()) };
+
+    // Names currently hidden, kept while their widgets are rebuilt
+    static HIDDEN: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+
+    static ANIMATIONS: RefCell<Vec<Animation>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Keeps a widget registered until dropped
+pub struct Registration {
+    name: String,
+    widget: Rc<dyn Toggle>,
+}
+
+impl Watch for Registration {}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _ = WIDGETS.try_with(|widgets| {
+            widgets
+                .borrow_mut()
+                .retain(|(name, widget)| !(*name == self.name && Rc::ptr_eq(widget, &self.widget)))
+        });
+    }
+}
+
+/// Make a widget reachable by name from Lua and the command line
+pub fn register(name: &str, widget: Rc<dyn Toggle>) -> Registration {
+    WIDGETS.with(|widgets| {
+        widgets
+            .borrow_mut()
+            .push((name.to_string(), widget.clone()))
+    });
+    Registration {
+        name: name.to_string(),
+        widget,
+    }
+}
+
+/// Whether widgets with this name should be on screen
+pub fn is_visible(name: &str) -> bool {
+    HIDDEN.with(|hidden| !hidden.borrow().contains(name))
+}
+
+/// Start the named widgets hidden, as set in the config
+pub fn hide_at_start(names: impl IntoIterator<Item = String>) {
+    HIDDEN.with(|hidden| hidden.borrow_mut().extend(names));
+}
+
+/// Show or hide every widget registered under `name`
+pub fn set_visible(name: &str, visible: bool) -> Result<(), String> {
+    let targets: Vec<Rc<dyn Toggle>> = WIDGETS.with(|widgets| {
+        widgets
+            .borrow()
+            .iter()
+            .filter(|(other, _)| other == name)
+            .map(|(_, widget)| widget.clone())
+            .collect()
+    });
+    if targets.is_empty() {
+        return Err(format!("No widget named {}", name));
+    }
+
+    info!(
+        "{} widget {}",
+        if visible { "Showing" } else { "Hiding" },
+        name
+    );
+    HIDDEN.with(|hidden| {
+        let mut hidden = hidden.borrow_mut();
+        if visible {
+            hidden.remove(name);
+        } else {
+            hidden.insert(name.to_string());
+        }
+    });
+    for widget in targets {
+        widget.set_visible(visible);
+    }
+    Ok(())
+}
+
+/// Flip the visibility of a widget, returning whether it is now shown
+pub fn toggle(name: &str) -> Result<bool, String> {
+    let visible = !is_visible(name);
+    set_visible(name, visible)?;
+    Ok(visible)
+}
+
+/// Names of the registered widgets, sorted
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = WIDGETS.with(|widgets| {
+        widgets
+            .borrow()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    });
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Map a window, running its transition in
+pub fn show_window(window: &gtk::Window, transition: Transition) {
+    animate(window, transition, true);
+}
+
+/// Run a window's transition out, then unmap it
+pub fn hide_window(window: &gtk::Window, transition: Transition) {
+    animate(window, transition, false);
+}
+
+// A running transition. Progress goes from 0 (hidden) to 1 (shown).
+struct Animation {
+    window: gtk::Window,
+    source: glib::SourceId,
+    progress: Rc<Cell<f64>>,
+    effect: Effect,
+}
+
+#[derive(Clone, Copy)]
+enum Effect {
+    Fade,
+    // Moves the window past the edge it is anchored to: the edge, its
+    // resting margin and the distance to travel
+    Slide(Edge, i32, i32),
+}
+
+impl Effect {
+    fn apply(self, window: &gtk::Window, progress: f64) {
+        match self {
+            Effect::Fade => window.set_opacity(progress),
+            Effect::Slide(edge, rest, extent) => {
+                let offset = ((1.0 - progress) * f64::from(rest + extent)).round() as i32;
+                window.set_layer_shell_margin(edge, rest - offset);
+            }
+        }
+    }
+}
+
+fn animate(window: &gtk::Window, transition: Transition, showing: bool) {
+    // Carry on from where an interrupted transition left the window
+    let interrupted = stop_animation(window);
+    let effect = match (transition, interrupted) {
+        (Transition::None, _) => None,
+        (_, Some((_, effect))) => Some(effect),
+        (Transition::Fade(_), None) => Some(Effect::Fade),
+        (Transition::Slide(_), None) => Some(slide_effect(window)),
+    };
+    let duration = transition.duration();
+    let Some(effect) = effect.filter(|_| duration > 0) else {
+        if let Some((_, effect)) = interrupted {
+            effect.apply(window, 1.0);
+        }
+        if showing {
+            window.show_all();
+        } else {
+            window.hide();
+        }
+        return;
+    };
+
+    let target = if showing { 1.0 } else { 0.0 };
+    let from = match interrupted {
+        Some((progress, _)) => progress,
+        None if window.is_visible() => 1.0,
+        None => 0.0,
+    };
+    effect.apply(window, from);
+    if showing {
+        window.show_all();
+    }
+
+    let progress = Rc::new(Cell::new(from));
+    let total = duration as f64 * (target - from).abs();
+    let started = glib::monotonic_time();
+    let source = {
+        let window = window.clone();
+        let progress = progress.clone();
+        glib::timeout_add_local(FRAME, move || {
+            let elapsed = (glib::monotonic_time() - started) as f64 / 1000.0;
+            let step = if total > 0.0 {
+                (elapsed / total).min(1.0)
+            } else {
+                1.0
+            };
+            progress.set(from + (target - from) * step);
+            effect.apply(&window, progress.get());
+            if step < 1.0 {
+                return glib::ControlFlow::Continue;
+            }
+
+            ANIMATIONS.with(|animations| {
+                animations
+                    .borrow_mut()
+                    .retain(|animation| animation.window != window)
+            });
+            if !showing {
+                window.hide();
+                effect.apply(&window, 1.0);
+            }
+            glib::ControlFlow::Break
+        })
+    };
+    ANIMATIONS.with(|animations| {
+        animations.borrow_mut().push(Animation {
+            window: window.clone(),
+            source,
+            progress,
+            effect,
+        })
+    });
+}
+
+// Cancel a running transition, returning how far it got
+fn stop_animation(window: &gtk::Window) -> Option<(f64, Effect)> {
+    let animation = ANIMATIONS.with(|animations| {
+        let mut animations = animations.borrow_mut();
+        let index = animations
+            .iter()
+            .position(|animation| animation.window == *window)?;
+        Some(animations.remove(index))
+    })?;
+    animation.source.remove();
+    Some((animation.progress.get(), animation.effect))
+}
+
+// Slide towards the anchored edge, preferring the top and bottom. Windows
+// anchored on no edge fade instead.
+fn slide_effect(window: &gtk::Window) -> Effect {
+    let (width, height) = window.size();
+    [
+        (Edge::Top, height),
+        (Edge::Bottom, height),
+        (Edge::Left, width),
+        (Edge::Right, width),
+    ]
+    .into_iter()
+    .find(|&(edge, _)| window.is_anchor(edge))
+    .map_or(Effect::Fade, |(edge, extent)| {
+        Effect::Slide(edge, window.layer_shell_margin(edge), extent)
+    })
+}